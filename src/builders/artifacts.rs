@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use ethers_solc::ConfigurableContractArtifact;
+use tiny_keccak::{Hasher, Keccak};
+use walkdir::WalkDir;
+
+use crate::builders::build::{BuildOutput, ProjectConfig};
+use crate::builders::detect::HARDHAT_CONFIGS;
+use crate::builders::forge::{process_out_directory, ForgeBuilder};
+use crate::builders::hardhat::{self, process_artifacts_directory, HardhatBuilder, HardhatMode};
+use crate::builders::runner::Runner;
+use crate::builders::truffle::{self, process_truffle_directory, TruffleBuilder};
+
+// Build output a repository may have committed, by the tool that wrote it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArtifactLayout {
+    Forge,
+    Hardhat,
+    Truffle,
+}
+
+const LAYOUTS: [ArtifactLayout; 3] = [ArtifactLayout::Forge, ArtifactLayout::Hardhat, ArtifactLayout::Truffle];
+
+// Where a layout's artifacts and sources are, relative to the project root.
+#[derive(Clone, Debug)]
+pub struct ArtifactPaths {
+    pub artifacts: PathBuf,
+    pub sources: PathBuf,
+}
+
+// What an artifact records about a source file it was compiled from.
+enum Fingerprint {
+    Content(String),
+    Keccak256(String),
+}
+
+impl fmt::Display for ArtifactLayout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArtifactLayout::Forge => write!(f, "forge"),
+            ArtifactLayout::Hardhat => write!(f, "hardhat"),
+            ArtifactLayout::Truffle => write!(f, "truffle"),
+        }
+    }
+}
+
+impl ArtifactLayout {
+    // Default locations, relative to the project root.
+    fn default_paths(&self) -> ArtifactPaths {
+        ArtifactPaths { artifacts: PathBuf::from(self.artifacts_dir()), sources: PathBuf::from(self.sources_dir()) }
+    }
+
+    fn artifacts_dir(&self) -> &'static str {
+        match self {
+            ArtifactLayout::Forge => "out",
+            ArtifactLayout::Hardhat => "artifacts",
+            ArtifactLayout::Truffle => "build/contracts",
+        }
+    }
+
+    fn sources_dir(&self) -> &'static str {
+        match self {
+            ArtifactLayout::Forge => "src",
+            ArtifactLayout::Hardhat | ArtifactLayout::Truffle => "contracts",
+        }
+    }
+
+    // Whether the project's config may move artifacts or sources, going by the keys that do so
+    // appearing in it. Cheap, so resolve_paths only starts forge or node when it might matter.
+    fn config_sets_paths(&self, project: &Path) -> bool {
+        let configs: Vec<PathBuf> = match self {
+            ArtifactLayout::Forge => vec![project.join("foundry.toml")],
+            ArtifactLayout::Hardhat => HARDHAT_CONFIGS.iter().map(|file| project.join(file)).collect(),
+            ArtifactLayout::Truffle => truffle::config_file(project).into_iter().collect(),
+        };
+        configs.iter().filter_map(|config| fs::read_to_string(config).ok()).any(|content| match self {
+            ArtifactLayout::Forge => content.lines().any(|line| {
+                let key = line.split('=').next().unwrap_or_default().trim();
+                line.contains('=') && (key == "out" || key == "src")
+            }),
+            ArtifactLayout::Hardhat => content.contains("paths"),
+            ArtifactLayout::Truffle => content.contains("contracts_build_directory") || content.contains("contracts_directory"),
+        })
+    }
+
+    // Where the project's config puts artifacts and sources. Forge and truffle configs load
+    // without dependencies. Hardhat's needs them installed, so until they are, and whenever a
+    // config can't be loaded, the defaults are assumed.
+    pub fn resolve_paths(&self, project: &Path, runner: &Runner) -> ArtifactPaths {
+        let directory = project.to_string_lossy();
+        let resolved = match self {
+            ArtifactLayout::Forge if project.join("foundry.toml").exists() => ForgeBuilder
+                .resolve_config(&directory, runner)
+                .map(|config| (config.out, Some(config.src))),
+            ArtifactLayout::Hardhat if HARDHAT_CONFIGS.iter().any(|file| project.join(file).exists()) && project.join("node_modules/hardhat").is_dir() => {
+                HardhatBuilder::new(HardhatMode::detect(project).unwrap_or(HardhatMode::Npm))
+                    .resolve_config(&directory, runner)
+                    .map(|config| (config.paths.artifacts, Some(config.paths.sources)))
+            }
+            ArtifactLayout::Truffle if truffle::config_file(project).is_some() => TruffleBuilder
+                .resolve_config(&directory, runner)
+                .and_then(|config| config.contracts_build_directory.map(|artifacts| (artifacts, config.contracts_directory))),
+            _ => None,
+        };
+        let defaults = self.default_paths();
+        match resolved {
+            Some((artifacts, sources)) => ArtifactPaths {
+                artifacts: relative_to(project, Path::new(&artifacts)),
+                sources: sources.map(|sources| relative_to(project, Path::new(&sources))).unwrap_or(defaults.sources),
+            },
+            None => defaults,
+        }
+    }
+
+    // Source path, relative to the project root, to what the artifacts say it contained.
+    fn fingerprints(&self, artifacts: &Path) -> HashMap<String, Fingerprint> {
+        let mut fingerprints = HashMap::new();
+        let json_files = WalkDir::new(artifacts)
+            .into_iter()
+            .flatten()
+            .filter(|entry| entry.path().is_file() && entry.path().extension() == Some("json".as_ref()));
+
+        for entry in json_files {
+            let path = entry.path();
+            match self {
+                ArtifactLayout::Forge => {
+                    let sources = ethers_solc::utils::read_json_file::<ConfigurableContractArtifact>(path)
+                        .ok()
+                        .and_then(|artifact| artifact.metadata)
+                        .map(|metadata| metadata.sources.inner)
+                        .unwrap_or_default();
+                    for (source, hash) in sources {
+                        fingerprints.insert(source, Fingerprint::Keccak256(hash.keccak256));
+                    }
+                }
+                ArtifactLayout::Hardhat => {
+                    if !path.starts_with(artifacts.join("build-info")) {
+                        continue;
+                    }
+                    let build_info = fs::read_to_string(path).ok().and_then(|content| serde_json::from_str::<hardhat::Metadata>(&content).ok());
+                    for (source, input) in build_info.map(|info| info.input.sources).unwrap_or_default() {
+                        fingerprints.insert(source, Fingerprint::Content(input.content));
+                    }
+                }
+                ArtifactLayout::Truffle => {
+                    if let Some(artifact) = truffle::read_artifact(path) {
+                        // Truffle 5 records project:/ paths, older ones the committer's absolute path
+                        // which we can't map back to the checkout.
+                        if let Some(source) = artifact.ast.absolute_path.as_ref().and_then(|p| p.strip_prefix("project:/")) {
+                            fingerprints.insert(source.to_string(), Fingerprint::Content(artifact.source));
+                        }
+                    }
+                }
+            }
+        }
+        fingerprints
+    }
+
+    // Whether the artifacts were built from the sources as they are in the checkout. Every
+    // source file has to be covered and every covered file has to be unchanged. Dependencies
+    // that aren't in the checkout, like uninitialised submodules under lib/, are skipped and
+    // end up in the results' missing sources.
+    pub fn check(&self, project: &Path, paths: &ArtifactPaths) -> Result<(), String> {
+        let artifacts = project.join(&paths.artifacts);
+        let fingerprints = self.fingerprints(&artifacts);
+        if fingerprints.is_empty() {
+            return Err(format!("no {} artifacts in {}", self, artifacts.to_string_lossy()));
+        }
+
+        for source in solidity_files(&project.join(&paths.sources)) {
+            let relative = source.strip_prefix(project).unwrap_or(&source).to_string_lossy().to_string();
+            if !fingerprints.contains_key(&relative) {
+                return Err(format!("{} has no artifact", relative));
+            }
+        }
+
+        for (source, fingerprint) in &fingerprints {
+            let contents = match fs::read(project.join(source)) {
+                Ok(contents) => contents,
+                Err(_) if Path::new(source).starts_with(&paths.sources) => return Err(format!("{} is missing", source)),
+                Err(_) => continue,
+            };
+            let unchanged = match fingerprint {
+                Fingerprint::Content(content) => content.as_bytes() == contents.as_slice(),
+                Fingerprint::Keccak256(hash) => hash.trim_start_matches("0x").eq_ignore_ascii_case(&keccak256_hex(&contents)),
+            };
+            if !unchanged {
+                return Err(format!("{} changed since the artifacts were built", source));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn ingest(&self, directory: &str, paths: &ArtifactPaths) -> BuildOutput {
+        let artifacts = Path::new(directory).join(&paths.artifacts);
+        let result = match self {
+            ArtifactLayout::Forge => process_out_directory(directory, &artifacts),
+            ArtifactLayout::Hardhat => process_artifacts_directory(directory, &artifacts),
+            ArtifactLayout::Truffle => process_truffle_directory(directory, &artifacts),
+        };
+        let config = ProjectConfig {
+            artifacts: artifacts.to_string_lossy().to_string(),
+            sources: vec![paths.sources.to_string_lossy().to_string()],
+            ..Default::default()
+        };
+        BuildOutput::new(result, Some(config))
+    }
+}
+
+// Read committed artifacts in directory without compiling, at the paths the project's config
+// sets. Configs are only evaluated when they look like they move the paths, as this runs before
+// every build. Artifacts are only used when they match the sources, unless force is set, in
+// which case stale ones are taken too.
+pub fn committed_artifacts(directory: &str, force: bool, runner: &Runner) -> Option<(ArtifactLayout, BuildOutput)> {
+    let project = Path::new(directory);
+    for layout in LAYOUTS {
+        let paths = if layout.config_sets_paths(project) { layout.resolve_paths(project, runner) } else { layout.default_paths() };
+        if !project.join(&paths.artifacts).is_dir() {
+            continue;
+        }
+        match layout.check(project, &paths) {
+            Ok(()) => log::info!("Committed {} artifacts in {} match the sources", layout, directory),
+            Err(reason) if force => log::warn!("Using committed {} artifacts in {} anyway: {}", layout, directory, reason),
+            Err(reason) => {
+                log::info!("Not using committed {} artifacts in {}: {}", layout, directory, reason);
+                continue;
+            }
+        }
+        let output = layout.ingest(directory, &paths);
+        if !output.contracts.is_empty() {
+            return Some((layout, output));
+        }
+    }
+    None
+}
+
+// Configs resolve paths to absolute ones, which are compared against paths relative to the project.
+fn relative_to(project: &Path, path: &Path) -> PathBuf {
+    match fs::canonicalize(project) {
+        Ok(root) if path.is_absolute() => path.strip_prefix(&root).map(Path::to_path_buf).unwrap_or_else(|_| path.to_path_buf()),
+        _ => path.to_path_buf(),
+    }
+}
+
+fn solidity_files(directory: &Path) -> Vec<PathBuf> {
+    WalkDir::new(directory)
+        .into_iter()
+        .flatten()
+        .map(|entry| entry.into_path())
+        .filter(|path| path.is_file() && path.extension() == Some("sol".as_ref()))
+        .collect()
+}
+
+fn keccak256_hex(data: &[u8]) -> String {
+    let mut hasher = Keccak::v256();
+    let mut hash = [0u8; 32];
+    hasher.update(data);
+    hasher.finalize(&mut hash);
+    hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
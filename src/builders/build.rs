@@ -1,5 +1,31 @@
+use serde::Serialize;
+
+use crate::builders::runner::Runner;
 use crate::contract::Contract;
 
+// Settings a builder resolved from the project's own configuration rather than assumed.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ProjectConfig {
+    pub artifacts: String,
+    pub sources: Vec<String>,
+    pub libraries: Vec<String>,
+    pub remappings: Vec<String>,
+    pub compilers: Vec<String>,
+}
+
+// What a builder produced in a directory.
+pub struct BuildOutput {
+    pub directory: String,
+    pub contracts: Vec<Contract>,
+    pub config: Option<ProjectConfig>,
+}
+
+impl BuildOutput {
+    pub fn new((directory, contracts): (String, Vec<Contract>), config: Option<ProjectConfig>) -> Self {
+        Self { directory, contracts, config }
+    }
+}
+
 pub trait Build {
-    fn build(&self, directory: &str) -> Result<(String, Vec<Contract>), Box<dyn std::error::Error>>;
-}
\ No newline at end of file
+    fn build(&self, directory: &str, runner: &Runner) -> Result<BuildOutput, Box<dyn std::error::Error>>;
+}
@@ -0,0 +1,184 @@
+use std::cmp::Reverse;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::builders::hardhat::PackageJson;
+use crate::builders::truffle;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum BuilderKind {
+    Hardhat,
+    Forge,
+    Truffle,
+}
+
+// Also the order ties are broken in.
+const BUILDERS: [BuilderKind; 3] = [BuilderKind::Hardhat, BuilderKind::Forge, BuilderKind::Truffle];
+
+// A config file is what makes a directory buildable, the other signals only order candidates.
+const CONFIG_SCORE: u32 = 10;
+pub const HARDHAT_CONFIGS: [&str; 4] = ["hardhat.config.ts", "hardhat.config.js", "hardhat.config.cjs", "hardhat.config.mjs"];
+const FORGE_CONFIG: &str = "foundry.toml";
+// Package manager files, recorded alongside the builder configs.
+const PACKAGE_FILES: [&str; 8] = ["package.json", "package-lock.json", "yarn.lock", ".yarnrc.yml", "pnpm-lock.yaml", "pnpm-workspace.yaml", "bun.lockb", "bun.lock"];
+
+impl fmt::Display for BuilderKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuilderKind::Hardhat => write!(f, "hardhat"),
+            BuilderKind::Forge => write!(f, "forge"),
+            BuilderKind::Truffle => write!(f, "truffle"),
+        }
+    }
+}
+
+impl BuilderKind {
+    // Programs the builder runs.
+    pub fn tools(&self) -> &'static [&'static str] {
+        match self {
+            BuilderKind::Hardhat => &["node", "npm", "npx"],
+            BuilderKind::Forge => &["forge"],
+            BuilderKind::Truffle => &["node", "npm", "truffle"],
+        }
+    }
+}
+
+// How strongly a directory looks like it is built with one builder, and why.
+#[derive(Clone, Debug, Serialize)]
+pub struct Candidate {
+    pub builder: BuilderKind,
+    pub score: u32,
+    pub signals: Vec<String>,
+}
+
+impl Candidate {
+    fn new(builder: BuilderKind) -> Self {
+        Self { builder, score: 0, signals: Vec::new() }
+    }
+
+    fn signal(&mut self, present: bool, score: u32, signal: &str) {
+        if present {
+            self.score += score;
+            self.signals.push(signal.to_string());
+        }
+    }
+}
+
+// Build configuration files found in a directory while looking for a builder, and the
+// builders they point to, best first.
+#[derive(Clone, Debug, Serialize)]
+pub struct Detection {
+    pub directory: String,
+    pub config_files: Vec<String>,
+    pub candidates: Vec<Candidate>,
+}
+
+impl Detection {
+    pub fn scan(directory: &Path) -> Self {
+        // The same files the candidates are scored on, so the trace shows every config that counted.
+        let config_files = HARDHAT_CONFIGS
+            .iter()
+            .chain([FORGE_CONFIG].iter())
+            .chain(truffle::CONFIG_FILES.iter())
+            .chain(PACKAGE_FILES.iter())
+            .filter(|file| directory.join(file).exists())
+            .map(|file| file.to_string())
+            .collect();
+
+        let package = PackageJson::read(&directory.join("package.json")).unwrap_or_default();
+        let gitmodules = fs::read_to_string(directory.join(".gitmodules")).unwrap_or_default();
+        let mut candidates: Vec<Candidate> = BUILDERS
+            .iter()
+            .map(|builder| {
+                let mut candidate = Candidate::new(*builder);
+                match builder {
+                    BuilderKind::Hardhat => {
+                        let config = HARDHAT_CONFIGS.iter().find(|file| directory.join(file).exists());
+                        candidate.signal(config.is_some(), CONFIG_SCORE, config.unwrap_or(&"hardhat config"));
+                        candidate.signal(package.depends_on("hardhat"), 5, "hardhat dependency");
+                        candidate.signal(directory.join("artifacts/build-info").is_dir(), 1, "artifacts/build-info");
+                    }
+                    BuilderKind::Forge => {
+                        candidate.signal(directory.join(FORGE_CONFIG).exists(), CONFIG_SCORE, FORGE_CONFIG);
+                        candidate.signal(directory.join("lib/forge-std").is_dir(), 3, "lib/forge-std");
+                        candidate.signal(gitmodules.contains("forge-std"), 2, "forge-std submodule");
+                        candidate.signal(directory.join("remappings.txt").exists(), 2, "remappings.txt");
+                    }
+                    BuilderKind::Truffle => {
+                        let config = truffle::config_file(directory);
+                        let name = config.as_ref().and_then(|file| file.file_name()).map(|name| name.to_string_lossy().to_string());
+                        candidate.signal(config.is_some(), CONFIG_SCORE, name.as_deref().unwrap_or("truffle config"));
+                        candidate.signal(package.depends_on("truffle"), 5, "truffle dependency");
+                        candidate.signal(directory.join("migrations").is_dir(), 2, "migrations");
+                    }
+                }
+                candidate
+            })
+            .filter(|candidate| candidate.score > 0)
+            .collect();
+        // Stable, so equal scores keep the BUILDERS order.
+        candidates.sort_by_key(|candidate| Reverse(candidate.score));
+
+        Self { directory: directory.to_string_lossy().to_string(), config_files, candidates }
+    }
+
+    // Builders whose config file is in the directory, best first.
+    pub fn detected(&self) -> Vec<BuilderKind> {
+        self.candidates.iter().filter(|candidate| candidate.score >= CONFIG_SCORE).map(|candidate| candidate.builder).collect()
+    }
+
+    pub fn is_project(&self) -> bool {
+        !self.detected().is_empty()
+    }
+}
+
+// How a repository's builders were chosen. Kept with the build result and failure record so
+// a surprising choice can be explained.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Decision {
+    // The directory that was built, None if no project was found.
+    pub project: Option<String>,
+    // Every directory looked at on the way to the project.
+    pub detections: Vec<Detection>,
+    // Builders in the order they were tried.
+    pub chain: Vec<BuilderKind>,
+    pub reason: String,
+}
+
+impl Decision {
+    // Order the builders for the project directory. An explicit builder replaces detection,
+    // otherwise detected builders are tried best first followed by the fallback chain.
+    pub fn plan(&mut self, builder: Option<BuilderKind>, fallback: &[BuilderKind]) {
+        let detection = match self.detections.last() {
+            Some(detection) => detection,
+            None => return,
+        };
+        if let Some(builder) = builder {
+            self.chain = vec![builder];
+            self.reason = format!("{} was asked for", builder);
+            return;
+        }
+
+        let mut chain = detection.detected();
+        let detected = chain
+            .iter()
+            .filter_map(|builder| detection.candidates.iter().find(|candidate| candidate.builder == *builder))
+            .map(|candidate| format!("{} (score {}: {})", candidate.builder, candidate.score, candidate.signals.join(", ")))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let fallback: Vec<BuilderKind> = fallback.iter().filter(|builder| !chain.contains(builder)).copied().collect();
+        chain.extend(&fallback);
+
+        self.reason = if fallback.is_empty() {
+            format!("detected {}", detected)
+        } else {
+            let fallback = fallback.iter().map(|builder| builder.to_string()).collect::<Vec<_>>().join(", ");
+            format!("detected {}, falling back to {}", detected, fallback)
+        };
+        self.chain = chain;
+    }
+}
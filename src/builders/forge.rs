@@ -1,4 +1,3 @@
-use std::process::{Command, exit};
 use ethers_solc::artifacts::NodeType;
 use log;
 use std::path::Path;
@@ -6,35 +5,60 @@ use std::env;
 use std::collections::HashMap;
 use walkdir::WalkDir;
 use ethers_solc::ConfigurableContractArtifact;
+use serde_derive::Deserialize;
 
-use crate::builders::build::Build;
+use crate::builders::build::{Build, BuildOutput, ProjectConfig};
+use crate::builders::runner::Runner;
 use crate::contract::{Contract, Kind};
 
 pub struct ForgeBuilder;
 
-impl Build for ForgeBuilder {
-    fn build(&self, directory: &str) -> Result<(String, Vec<Contract>), Box<dyn std::error::Error>> {
-        log::info!("Executing forge install in {}", directory);
-        let install_result = Command::new("forge")
-            .arg("install")
-            .current_dir(directory)
-            .output();
-    
-        if let Err(err) = install_result {
-            log::error!("Error executing 'forge install': {}", err);
-            exit(1);
+// The parts of `forge config --json` we use. Paths are relative to the project root and
+// already reflect the selected profile, environment overrides and remappings.txt.
+#[derive(Debug, Deserialize)]
+pub struct ForgeConfig {
+    pub src: String,
+    pub out: String,
+    #[serde(default)]
+    pub libs: Vec<String>,
+    #[serde(default)]
+    pub remappings: Vec<String>,
+    pub cache_path: Option<String>,
+    // A version, a path to a solc binary, or null for auto-detection.
+    pub solc: Option<serde_json::Value>,
+}
+
+impl ForgeBuilder {
+    // None when forge can't load the config, in which case the defaults are assumed.
+    pub fn resolve_config(&self, directory: &str, runner: &Runner) -> Option<ForgeConfig> {
+        let output = runner.output("forge", &["config", "--json"], directory)?;
+        match serde_json::from_str(&output) {
+            Ok(config) => Some(config),
+            Err(err) => {
+                log::error!("Error parsing forge config for {}: {}", directory, err);
+                None
+            }
         }
+    }
+}
+
+impl Build for ForgeBuilder {
+    fn build(&self, directory: &str, runner: &Runner) -> Result<BuildOutput, Box<dyn std::error::Error>> {
+        runner.install("forge", &["install"], directory);
     
+        // foundry.toml can move out and cache, and FOUNDRY_PROFILE picks between profiles.
+        let resolved = self.resolve_config(directory, runner);
+        let (out, cache) = match &resolved {
+            Some(config) => {
+                log::info!("Forge config for {}: src {}, out {}, libs {}", directory, config.src, config.out, config.libs.join(", "));
+                (config.out.clone(), config.cache_path.clone().unwrap_or_else(|| "cache".to_string()))
+            }
+            None => ("out".to_string(), "cache".to_string()),
+        };
+
         // Execute `forge build` in the repository directory
-        log::info!("Executing forge build in {}", directory);
-        let build_result = Command::new("forge")
-            .arg("build")
-            .current_dir(directory)
-            .output();
-    
-        if let Err(err) = build_result {
-            log::error!("Error executing 'forge build': {}", err);
-            exit(1);
+        if !runner.run("forge", &["build"], directory) {
+            log::error!("forge build failed in {}, collecting whatever was built", directory);
         }
     
         if let Ok(current_dir) = env::current_dir() {
@@ -43,36 +67,42 @@ impl Build for ForgeBuilder {
             log::error!("Failed to get current directory");
         }
     
-        let cache_dir = Path::new(&directory).join("cache");
+        let cache_dir = Path::new(&directory).join(&cache);
         if !cache_dir.exists() || !cache_dir.is_dir() {
-            log::error!("Error: 'cache' directory not found in {}", directory);
+            log::error!("Error: cache directory {} not found in {}", cache_dir.to_string_lossy(), directory);
             //exit(1);
         }
         
-        // Check if "out" directory exists
-        let out_dir = Path::new(&directory).join("out");
+        // Check if the out directory exists
+        let out_dir = Path::new(&directory).join(&out);
         log::info!("Checking for the out directory {}", out_dir.to_string_lossy());
         if !out_dir.exists() {
-            log::error!("Error: 'out' directory {} not found in {}", out_dir.to_string_lossy(), directory);
+            log::error!("Error: out directory {} not found in {}", out_dir.to_string_lossy(), directory);
             //exit(1);
         }
 
-        let result = process_out_directory(directory);
-        Ok(result)
+        let result = process_out_directory(directory, &out_dir);
+        let config = resolved.map(|config| ProjectConfig {
+            artifacts: config.out,
+            sources: vec![config.src],
+            libraries: config.libs,
+            remappings: config.remappings,
+            compilers: config.solc.as_ref().and_then(|solc| solc.as_str()).map(str::to_string).into_iter().collect(),
+        });
+        Ok(BuildOutput::new(result, config))
         
     }
 
  
 }
 
-pub fn process_out_directory(repo_directory: &str) -> (String, Vec<Contract>) {
-    let out_dir = Path::new(&repo_directory).join("out");
+pub fn process_out_directory(repo_directory: &str, out_dir: &Path) -> (String, Vec<Contract>) {
     log::info!("Looking for built contracts in {}", &out_dir.to_string_lossy());
 
     // Contract map stores a mapping from contract name to Contract.
     let mut contract_map: HashMap<String, Contract> = HashMap::new();
 
-    let walker = WalkDir::new(&out_dir).into_iter();
+    let walker = WalkDir::new(out_dir).into_iter();
 
     // First pass will find all json files, parse them and add them to a contract_map
     // Contract imports are None at this stage as they are populated in the second pass. 
@@ -144,9 +174,16 @@ pub fn process_out_directory(repo_directory: &str) -> (String, Vec<Contract>) {
                                 }
                             };
 
-                            // Read the file bytes and store in file_contents 
+                            // Read the file bytes and store in file_contents. A source that isn't in the
+                            // checkout, e.g. a dependency that wasn't installed, is reported in the results.
                             let file_contents_path = Path::new(&repo_directory).join(&ast.absolute_path);
-                            let file_contents = std::fs::read_to_string(file_contents_path).unwrap();
+                            let file_contents = match std::fs::read_to_string(&file_contents_path) {
+                                Ok(contents) => Some(contents),
+                                Err(err) => {
+                                    log::warn!("Unable to read the source of {} at {}: {}", contract_name, file_contents_path.to_string_lossy(), err);
+                                    None
+                                }
+                            };
 
                             let contract = Contract {
                                 contract_name: contract_name.to_owned(),
@@ -158,7 +195,8 @@ pub fn process_out_directory(repo_directory: &str) -> (String, Vec<Contract>) {
                                 deployed_sourcemap: deployed_bytecode.source_map.to_owned(),
                                 absolute_path: Some(ast.absolute_path.to_owned()),
                                 id: metadata.id,
-                                file_contents: Some(file_contents),
+                                file_contents,
+                                source_hash: None,
                             };
                             contract_map.insert(contract_name.to_owned(), contract);
                         }
@@ -174,7 +212,7 @@ pub fn process_out_directory(repo_directory: &str) -> (String, Vec<Contract>) {
 
     let contract_map_clone = contract_map.clone();
 
-    let walker = WalkDir::new(&out_dir).into_iter();
+    let walker = WalkDir::new(out_dir).into_iter();
 
     for entry in walker.flatten() {
         let entry_path = entry.path();
@@ -1,14 +1,13 @@
-use std::process::{Command, exit};
 use log;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::fs;
 use std::collections::HashMap;
 use walkdir::WalkDir;
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
 
-use crate::builders::build::Build;
-use crate::contract;
+use crate::builders::build::{Build, BuildOutput, ProjectConfig};
+use crate::builders::runner::{self, Runner};
 use crate::contract::{Contract, Kind};
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -115,83 +114,278 @@ pub struct Reference {
     pub start: u32,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum HardhatMode {
-    Yarn,
     Npm,
+    Yarn,
+    // Yarn 2+, which uses Plug'n'Play instead of node_modules unless told otherwise.
+    YarnBerry,
+    Pnpm,
+    Bun,
+}
+
+// The order package managers are tried in after the one the lockfile points at.
+const FALLBACK_ORDER: [HardhatMode; 4] = [HardhatMode::Npm, HardhatMode::Yarn, HardhatMode::Pnpm, HardhatMode::Bun];
+
+impl HardhatMode {
+    fn program(&self) -> &'static str {
+        match self {
+            HardhatMode::Npm => "npm",
+            HardhatMode::Yarn | HardhatMode::YarnBerry => "yarn",
+            HardhatMode::Pnpm => "pnpm",
+            HardhatMode::Bun => "bun",
+        }
+    }
+
+    // hardhat, run however this package manager runs local binaries.
+    fn hardhat_command(&self) -> &'static [&'static str] {
+        match self {
+            HardhatMode::Npm => &["npx", "hardhat"],
+            // With PnP there is no node_modules/.bin for npx to find hardhat in.
+            HardhatMode::Yarn | HardhatMode::YarnBerry => &["yarn", "hardhat"],
+            HardhatMode::Pnpm => &["pnpm", "exec", "hardhat"],
+            HardhatMode::Bun => &["bunx", "hardhat"],
+        }
+    }
+
+    // The package manager a directory's lockfile belongs to.
+    pub fn detect(directory: &Path) -> Option<Self> {
+        if directory.join("pnpm-lock.yaml").exists() {
+            Some(HardhatMode::Pnpm)
+        } else if directory.join("bun.lockb").exists() || directory.join("bun.lock").exists() {
+            Some(HardhatMode::Bun)
+        } else if directory.join("yarn.lock").exists() {
+            if is_yarn_berry(directory) {
+                Some(HardhatMode::YarnBerry)
+            } else {
+                Some(HardhatMode::Yarn)
+            }
+        } else if directory.join("package-lock.json").exists() {
+            Some(HardhatMode::Npm)
+        } else {
+            None
+        }
+    }
+}
+
+// Berry projects have a .yarnrc.yml and a lockfile starting with __metadata.
+fn is_yarn_berry(directory: &Path) -> bool {
+    directory.join(".yarnrc.yml").exists()
+        || fs::read_to_string(directory.join("yarn.lock")).is_ok_and(|lock| lock.contains("__metadata:"))
+}
+
+// Run with `hardhat run` to print the config hardhat resolved, including defaults and
+// anything computed in hardhat.config.ts. Written into the project so it can be found from
+// inside the sandbox.
+const CONFIG_SCRIPT: &str = r#"
+const { config } = hre;
+const overrides = Object.values(config.solidity.overrides || {});
+const compilers = [...new Set(config.solidity.compilers.concat(overrides).map((compiler) => compiler.version))];
+console.log(CONFIG_MARKER + JSON.stringify({ paths: config.paths, compilers }));
+"#;
+const CONFIG_SCRIPT_FILE: &str = ".rustarena-hardhat-config.js";
+const CONFIG_MARKER: &str = "RUSTARENA_CONFIG ";
+
+#[derive(Debug, Deserialize)]
+pub struct ResolvedConfig {
+    pub paths: ResolvedPaths,
+    pub compilers: Vec<String>,
+}
+
+// Absolute paths, as hardhat resolves them against the project root.
+#[derive(Debug, Deserialize)]
+pub struct ResolvedPaths {
+    pub sources: String,
+    pub artifacts: String,
+    pub cache: String,
 }
 
 pub struct HardhatBuilder {
     pub mode: HardhatMode,
+    // Where dependencies are installed. The monorepo root for workspace packages, otherwise
+    // the project directory itself.
+    pub install_root: Option<String>,
 }
 
 impl HardhatBuilder {
     pub fn new(mode: HardhatMode) -> Self {
-        Self { mode }
+        Self { mode, install_root: None }
     }
 
-    pub fn set_mode(&mut self, mode: HardhatMode) {
-        self.mode = mode;
+    pub fn set_install_root(&mut self, install_root: &Path) {
+        self.install_root = Some(install_root.to_string_lossy().to_string());
     }
 
-    pub fn flip_mode(&mut self) {
-        match self.mode {
-            HardhatMode::Yarn => self.mode = HardhatMode::Npm,
-            HardhatMode::Npm => self.mode = HardhatMode::Yarn,
+    // Modes to try for a project, in order. The lockfile's package manager comes first, then
+    // the rest of FALLBACK_ORDER that are installed. Yarn Berry stands in for Yarn when detected.
+    pub fn modes(directory: &Path, install_root: &Path) -> Vec<HardhatMode> {
+        let detected = HardhatMode::detect(directory).or_else(|| HardhatMode::detect(install_root));
+        let mut modes: Vec<HardhatMode> = detected.into_iter().collect();
+        for mode in FALLBACK_ORDER {
+            let mode = if mode == HardhatMode::Yarn && detected == Some(HardhatMode::YarnBerry) { HardhatMode::YarnBerry } else { mode };
+            if !modes.contains(&mode) && runner::on_path(mode.program()) {
+                modes.push(mode);
+            }
         }
+        modes
     }
 }
 
-impl Build for HardhatBuilder {
-    fn build(&self, directory: &str) -> Result<(String, Vec<Contract>), Box<dyn std::error::Error>> {
-        let (install_cmd, install_arg, compile_cmd, compile_arg) = match self.mode {
-            HardhatMode::Yarn => ("yarn", "install", "yarn", "compile"),
-            HardhatMode::Npm => ("npm", "install", "npx", "hardhat compile"),
-        };
+// The nearest directory from directory up to repo_root that declares workspaces, or
+// directory itself when it isn't part of a monorepo.
+pub fn workspace_root(directory: &Path, repo_root: &Path) -> PathBuf {
+    for ancestor in directory.ancestors() {
+        if !ancestor.starts_with(repo_root) {
+            break;
+        }
+        if is_workspace_root(ancestor) {
+            return ancestor.to_path_buf();
+        }
+    }
+    directory.to_path_buf()
+}
+
+pub fn is_workspace_root(directory: &Path) -> bool {
+    directory.join("pnpm-workspace.yaml").exists() || declares_workspaces(&directory.join("package.json"))
+}
 
-        log::info!("Executing {} in {}", install_cmd, directory);
-        let install_result = Command::new(install_cmd)
-            .arg(install_arg)
-            .current_dir(directory)
-            .output();
+// The parts of package.json we look at.
+#[derive(Default, Debug, Deserialize)]
+pub struct PackageJson {
+    #[serde(default)]
+    pub scripts: HashMap<String, String>,
+    pub workspaces: Option<serde_json::Value>,
+    #[serde(default)]
+    pub dependencies: HashMap<String, String>,
+    #[serde(default, rename = "devDependencies")]
+    pub dev_dependencies: HashMap<String, String>,
+}
 
-        if let Err(err) = install_result {
-            log::error!("Error executing '{}' with '{}': {}", install_cmd, install_arg, err);
-            exit(1);
+impl PackageJson {
+    pub fn read(path: &Path) -> Option<Self> {
+        let content = fs::read_to_string(path).ok()?;
+        match serde_json::from_str(&content) {
+            Ok(package) => Some(package),
+            Err(err) => {
+                log::error!("Error parsing {}: {}", path.to_string_lossy(), err);
+                None
+            }
         }
+    }
+
+    pub fn depends_on(&self, package: &str) -> bool {
+        self.dependencies.contains_key(package) || self.dev_dependencies.contains_key(package)
+    }
+
+    // The script the project compiles its contracts with. A script that runs hardhat wins,
+    // otherwise the first of the usual names. A bare "build" is often the frontend so it only
+    // counts when it mentions hardhat.
+    pub fn compile_script(&self) -> Option<&str> {
+        COMPILE_SCRIPTS
+            .iter()
+            .find(|name| self.scripts.get(**name).is_some_and(|script| script.contains("hardhat")))
+            .or_else(|| COMPILE_SCRIPTS.iter().filter(|name| **name != "build").find(|name| self.scripts.contains_key(**name)))
+            .copied()
+    }
+}
+
+// Script names projects use for compiling contracts, in order of preference.
+const COMPILE_SCRIPTS: [&str; 8] = ["compile", "build:contracts", "compile:contracts", "contracts:build", "contracts:compile", "build:sol", "hardhat:compile", "build"];
+
+fn declares_workspaces(package_json: &Path) -> bool {
+    package_json.exists() && PackageJson::read(package_json).is_some_and(|package| package.workspaces.is_some())
+}
+
+impl HardhatBuilder {
+    // Ask hardhat for its resolved config. Needs dependencies installed. None when the
+    // config can't be loaded, in which case hardhat's defaults are assumed.
+    pub fn resolve_config(&self, directory: &str, runner: &Runner) -> Option<ResolvedConfig> {
+        let script = Path::new(directory).join(CONFIG_SCRIPT_FILE);
+        let contents = format!("const CONFIG_MARKER = {:?};{}", CONFIG_MARKER, CONFIG_SCRIPT);
+        if let Err(err) = fs::write(&script, contents) {
+            log::error!("Error writing {}: {}", script.to_string_lossy(), err);
+            return None;
+        }
+        let mut command = self.mode.hardhat_command().to_vec();
+        command.extend(["run", "--no-compile", CONFIG_SCRIPT_FILE]);
+        let output = runner.output(command[0], &command[1..], directory);
+        fs::remove_file(&script).ok();
+
+        let line = output?.lines().find_map(|line| line.strip_prefix(CONFIG_MARKER).map(str::to_string))?;
+        match serde_json::from_str(&line) {
+            Ok(config) => Some(config),
+            Err(err) => {
+                log::error!("Error parsing the hardhat config for {}: {}", directory, err);
+                None
+            }
+        }
+    }
+}
+
+impl Build for HardhatBuilder {
+    fn build(&self, directory: &str, runner: &Runner) -> Result<BuildOutput, Box<dyn std::error::Error>> {
+        let install_root = self.install_root.as_deref().unwrap_or(directory);
+        let program = self.mode.program();
+        let mut install = vec!["install"];
+        if self.mode == HardhatMode::YarnBerry && !runner.scripts_allowed() {
+            install.push("--mode=skip-build");
+        }
+
+        // Workspace packages are installed from the root so hoisted and linked dependencies resolve.
+        runner.install(program, &install, install_root);
     
-        // Execute `forge build` in the repository directory
-        log::info!("Executing {} compile in {}", compile_cmd, directory);
-        let build_result = Command::new(compile_cmd)
-            .arg(compile_arg)
-            .current_dir(directory)
-            .output();
-    
-        if let Err(err) = build_result {
-            log::error!("Error executing '{}' with '{}': {}", compile_cmd, compile_arg, err);
-            exit(1);
+        // The project's own compile script knows about any pre-compile steps, so it goes first.
+        // Plain hardhat compile is the fallback when there isn't one or it built nothing.
+        // Projects can move artifacts, sources and the cache in their config.
+        let resolved = self.resolve_config(directory, runner);
+        let (artifacts_dir, cache_dir) = match &resolved {
+            Some(config) => {
+                log::info!("Hardhat config for {}: sources {}, artifacts {}, compilers {}", directory, config.paths.sources, config.paths.artifacts, config.compilers.join(", "));
+                (PathBuf::from(&config.paths.artifacts), PathBuf::from(&config.paths.cache))
+            }
+            None => (Path::new(directory).join("artifacts"), Path::new(directory).join("cache")),
+        };
+
+        let package = PackageJson::read(&Path::new(directory).join("package.json")).unwrap_or_default();
+        let script_built = match package.compile_script() {
+            Some(script) => {
+                log::info!("Using the {} script from package.json", script);
+                let succeeded = runner.run(program, &["run", script], directory);
+                succeeded && artifacts_dir.join("build-info").is_dir()
+            }
+            None => false,
+        };
+
+        let mut compile = self.mode.hardhat_command().to_vec();
+        compile.push("compile");
+        if !script_built && !runner.run(compile[0], &compile[1..], directory) {
+            log::error!("{} failed in {}, collecting whatever was built", compile.join(" "), directory);
         }
 
-        let cache_dir = Path::new(&directory).join("cache");
         if !cache_dir.exists() || !cache_dir.is_dir() {
-            log::error!("Error: 'cache' directory not found in {}", directory);
+            log::error!("Error: cache directory {} not found in {}", cache_dir.to_string_lossy(), directory);
             //exit(1);
         }
         
-        let artifacts_dir = Path::new(&directory).join("artifacts");
         log::info!("Checking for the artifacts directory {}", artifacts_dir.to_string_lossy());
         if !artifacts_dir.exists() {
-            log::error!("Error: 'artifacts' directory {} not found in {}", artifacts_dir.to_string_lossy(), directory);
+            log::error!("Error: artifacts directory {} not found in {}", artifacts_dir.to_string_lossy(), directory);
             //exit(1);
         }
 
-        let result = process_artifacts_directory(directory);
-        Ok(result)
+        let result = process_artifacts_directory(directory, &artifacts_dir);
+        let config = resolved.map(|config| ProjectConfig {
+            artifacts: config.paths.artifacts,
+            sources: vec![config.paths.sources],
+            compilers: config.compilers,
+            ..Default::default()
+        });
+        Ok(BuildOutput::new(result, config))
     }
 }
 
-pub fn process_artifacts_directory(repo_directory: &str) -> (String, Vec<Contract>) {
-    let build_dir = Path::new(&repo_directory).join("artifacts/build-info");
+pub fn process_artifacts_directory(repo_directory: &str, artifact_dir: &Path) -> (String, Vec<Contract>) {
+    let build_dir = artifact_dir.join("build-info");
     log::info!("Looking for built contracts in {}", &build_dir.to_string_lossy());
 
     // Contract map stores a mapping from contract name to Contract.
@@ -253,7 +447,8 @@ pub fn process_artifacts_directory(repo_directory: &str) -> (String, Vec<Contrac
                                                     deployed_sourcemap: Some(contract_info.evm.deployed_bytecode.source_map.to_owned()),
                                                     absolute_path: Some(source_info.ast.absolute_path.to_owned()),
                                                     id: Some(source_info.ast.id.to_owned()),
-                                                    file_contents: Some(input_info.content.to_owned()), 
+                                                    file_contents: Some(input_info.content.to_owned()),
+                                                    source_hash: None,
                                                 };
                                                 contract_map.insert(contract_name.to_owned(), contract);
                                             }
@@ -278,10 +473,9 @@ pub fn process_artifacts_directory(repo_directory: &str) -> (String, Vec<Contrac
 
     let contract_map_clone = contract_map.clone();
 
-    let artifact_dir = Path::new(&repo_directory).join("artifacts");
     log::info!("Looking for built contracts in {}", &artifact_dir.to_string_lossy());
 
-    let walker = WalkDir::new(&artifact_dir).into_iter();
+    let walker = WalkDir::new(artifact_dir).into_iter();
 
     for entry in walker.flatten() {
         let entry_path = entry.path();
@@ -308,8 +502,8 @@ pub fn process_artifacts_directory(repo_directory: &str) -> (String, Vec<Contrac
 
                         // LinkReferences contain the imports for a contract. If they aren't empty lets add them to the contract_map
                         if let Some(contract_object) = contract_map.get_mut(contract_name) {
-                            for (_contract_path, inner_map) in &metadata.link_references.contracts {
-                                for (contract_only, _references) in inner_map {
+                            for inner_map in metadata.link_references.contracts.values() {
+                                for contract_only in inner_map.keys() {
                                     if let Some(imported_contract) = contract_map_clone.get(contract_only) {
                                         contract_object.imports.get_or_insert_with(Vec::new).push(imported_contract.clone());
                                     } else {
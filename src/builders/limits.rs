@@ -0,0 +1,135 @@
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+// Resource limits applied to every command a builder runs.
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    // Wall clock time before the command and everything it started is killed.
+    pub timeout: Option<Duration>,
+    // Data segment limit in bytes (RLIMIT_DATA), or memory.max when a cgroup is used.
+    pub memory_bytes: Option<u64>,
+    // CPU time limit in seconds (RLIMIT_CPU).
+    pub cpu_seconds: Option<u64>,
+    // cgroup v2 directory we may create children in, e.g. /sys/fs/cgroup/rustarena.
+    pub cgroup_parent: Option<PathBuf>,
+}
+
+static CGROUP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// A cgroup created for one command. Removed again when dropped.
+pub struct Cgroup {
+    path: PathBuf,
+}
+
+impl Cgroup {
+    fn create(parent: &Path, memory_bytes: Option<u64>) -> io::Result<Self> {
+        if memory_bytes.is_some() {
+            enable_memory_controller(parent)?;
+        }
+        let index = CGROUP_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let path = parent.join(format!("rustarena-{}-{}", std::process::id(), index));
+        fs::create_dir_all(&path)?;
+        // Dropping it removes the directory again if setting the limit fails.
+        let cgroup = Self { path };
+        if let Some(memory_bytes) = memory_bytes {
+            fs::write(cgroup.path.join("memory.max"), memory_bytes.to_string())?;
+            fs::write(cgroup.path.join("memory.swap.max"), "0").ok();
+        }
+        Ok(cgroup)
+    }
+
+    // Kill everything in the cgroup, including processes that left the process group.
+    pub fn kill(&self) {
+        if let Err(err) = fs::write(self.path.join("cgroup.kill"), "1") {
+            log::error!("Error killing cgroup {}: {}", self.path.to_string_lossy(), err);
+        }
+    }
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        // Only succeeds once every process has exited, which is the case after wait.
+        if let Err(err) = fs::remove_dir(&self.path) {
+            log::debug!("Error removing cgroup {}: {}", self.path.to_string_lossy(), err);
+        }
+    }
+}
+
+// Children only get memory.max once the parent hands the memory controller down to them.
+fn enable_memory_controller(parent: &Path) -> io::Result<()> {
+    let available = fs::read_to_string(parent.join("cgroup.controllers"))?;
+    if !available.split_whitespace().any(|controller| controller == "memory") {
+        let message = format!("the memory controller isn't available in {}, it has to be delegated to it first", parent.to_string_lossy());
+        return Err(io::Error::new(io::ErrorKind::Unsupported, message));
+    }
+    let subtree_control = parent.join("cgroup.subtree_control");
+    if fs::read_to_string(&subtree_control)?.split_whitespace().any(|controller| controller == "memory") {
+        return Ok(());
+    }
+    fs::write(&subtree_control, "+memory")
+        .map_err(|err| io::Error::new(err.kind(), format!("unable to enable the memory controller in {}: {}", subtree_control.to_string_lossy(), err)))
+}
+
+impl Limits {
+    // Put the command in its own process group so it can be killed as a whole, apply the
+    // rlimits in the child before exec and join a fresh cgroup when one is configured.
+    pub fn apply(&self, command: &mut Command) -> Option<Cgroup> {
+        command.process_group(0);
+
+        let cgroup = self.cgroup_parent.as_ref().and_then(|parent| match Cgroup::create(parent, self.memory_bytes) {
+            Ok(cgroup) => Some(cgroup),
+            Err(err) => {
+                log::error!("Error creating cgroup under {}: {}", parent.to_string_lossy(), err);
+                None
+            }
+        });
+        let cgroup_procs = cgroup
+            .as_ref()
+            .and_then(|cgroup| CString::new(cgroup.path.join("cgroup.procs").to_string_lossy().as_bytes()).ok());
+
+        // The memory limit is enforced by the cgroup when there is one. RLIMIT_DATA rather than
+        // RLIMIT_AS because node reserves far more address space than it ever uses.
+        let memory_bytes = if cgroup.is_some() { None } else { self.memory_bytes };
+        let cpu_seconds = self.cpu_seconds;
+
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(procs) = &cgroup_procs {
+                    // Only async-signal-safe calls between fork and exec.
+                    let fd = libc::open(procs.as_ptr(), libc::O_WRONLY);
+                    if fd >= 0 {
+                        libc::write(fd, b"0".as_ptr() as *const libc::c_void, 1);
+                        libc::close(fd);
+                    }
+                }
+                if let Some(bytes) = memory_bytes {
+                    let limit = libc::rlimit { rlim_cur: bytes, rlim_max: bytes };
+                    if libc::setrlimit(libc::RLIMIT_DATA, &limit) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                if let Some(seconds) = cpu_seconds {
+                    let limit = libc::rlimit { rlim_cur: seconds, rlim_max: seconds };
+                    if libc::setrlimit(libc::RLIMIT_CPU, &limit) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+        cgroup
+    }
+}
+
+// SIGKILL the whole process group started for a command.
+pub fn kill_process_group(pid: u32) {
+    if unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL) } != 0 {
+        log::error!("Error killing process group {}: {}", pid, io::Error::last_os_error());
+    }
+}
@@ -0,0 +1,343 @@
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+
+use crate::builders::limits::{kill_process_group, Limits};
+use crate::builders::sandbox::Sandbox;
+
+// A compiler or package manager error pulled out of a command's output.
+#[derive(Clone, Debug, Serialize)]
+pub struct Diagnostic {
+    pub message: String,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+}
+
+// One command run by a builder, where its output went and whether it worked.
+#[derive(Clone, Debug, Serialize)]
+pub struct CommandRecord {
+    pub command: String,
+    pub directory: String,
+    pub success: bool,
+    pub exit_code: Option<i32>,
+    pub timed_out: bool,
+    pub duration_secs: f64,
+    pub error: Option<String>,
+    pub stdout_log: Option<String>,
+    pub stderr_log: Option<String>,
+    pub diagnostics: Vec<Diagnostic>,
+    // Run for its output, like a config lookup. Its failure isn't why a build failed.
+    pub probe: bool,
+}
+
+impl CommandRecord {
+    // One line explanation of a failed command for logs and reports.
+    pub fn summary(&self) -> String {
+        let status = match (&self.error, self.exit_code) {
+            (Some(error), _) => format!("could not be run: {}", error),
+            (None, _) if self.timed_out => format!("timed out after {:.0}s", self.duration_secs),
+            (None, Some(code)) => format!("exited with code {}", code),
+            (None, None) => "was killed".to_string(),
+        };
+        match self.diagnostics.first() {
+            Some(diagnostic) => match (&diagnostic.file, diagnostic.line) {
+                (Some(file), Some(line)) => format!("'{}' {}: {} ({}:{})", self.command, status, diagnostic.message, file, line),
+                _ => format!("'{}' {}: {}", self.command, status, diagnostic.message),
+            },
+            None => format!("'{}' {}", self.command, status),
+        }
+    }
+}
+
+// What a command is run for.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Build,
+    Install,
+    Probe,
+}
+
+// Runs builder commands for a single repository and keeps a record of each one.
+// stdout and stderr are written to numbered files in the log directory.
+pub struct Runner {
+    log_directory: PathBuf,
+    limits: Limits,
+    sandbox: Sandbox,
+    envs: Vec<(String, String)>,
+    records: RefCell<Vec<CommandRecord>>,
+}
+
+impl Runner {
+    pub fn new(log_directory: impl Into<PathBuf>, limits: Limits, sandbox: Sandbox) -> Self {
+        Self {
+            log_directory: log_directory.into(),
+            limits,
+            sandbox,
+            envs: Vec::new(),
+            records: RefCell::new(Vec::new()),
+        }
+    }
+
+    // Set an environment variable for every command run from here on.
+    pub fn set_env(&mut self, key: &str, value: &str) {
+        self.envs.push((key.to_string(), value.to_string()));
+    }
+
+    // Run program with args in directory. Returns whether it exited successfully, failures
+    // are logged and recorded rather than returned so the builder can decide what to do.
+    pub fn run(&self, program: &str, args: &[&str], directory: &str) -> bool {
+        self.run_step(program, args, directory, Step::Build)
+    }
+
+    // Run a dependency install. Installs are where untrusted lifecycle scripts run so the
+    // script policy applies, and they may be the only step allowed network access.
+    pub fn install(&self, program: &str, args: &[&str], directory: &str) -> bool {
+        let mut args = args.to_vec();
+        args.extend(self.sandbox.install_args(program, &args));
+        self.run_step(program, &args, directory, Step::Install)
+    }
+
+    // Run a command for what it prints. Returns stdout when it exits successfully. Recorded as
+    // a probe, so it isn't blamed when the build fails.
+    pub fn output(&self, program: &str, args: &[&str], directory: &str) -> Option<String> {
+        let index = self.records.borrow().len() + 1;
+        if self.run_step(program, args, directory, Step::Probe) {
+            fs::read_to_string(self.log_path(index, program, "stdout")).ok()
+        } else {
+            None
+        }
+    }
+
+    pub fn scripts_allowed(&self) -> bool {
+        self.sandbox.scripts_allowed()
+    }
+
+    fn run_step(&self, program: &str, args: &[&str], directory: &str, step: Step) -> bool {
+        let command = std::iter::once(program).chain(args.iter().copied()).collect::<Vec<_>>().join(" ");
+        log::info!("Executing {} in {}", command, directory);
+
+        let index = self.records.borrow().len() + 1;
+        let stdout_log = self.log_path(index, program, "stdout");
+        let stderr_log = self.log_path(index, program, "stderr");
+        let started = Instant::now();
+        let mut record = CommandRecord {
+            command,
+            directory: directory.to_string(),
+            success: false,
+            exit_code: None,
+            timed_out: false,
+            duration_secs: 0.0,
+            error: None,
+            stdout_log: None,
+            stderr_log: None,
+            diagnostics: Vec::new(),
+            probe: step == Step::Probe,
+        };
+
+        match self.execute(program, args, directory, step == Step::Install, &stdout_log, &stderr_log) {
+            Ok((status, timed_out)) => {
+                record.success = status.success() && !timed_out;
+                record.exit_code = status.code();
+                record.timed_out = timed_out;
+                if !record.success {
+                    // Output is on disk already, read it back for the diagnostics.
+                    let stderr = fs::read(&stderr_log).unwrap_or_default();
+                    let stdout = fs::read(&stdout_log).unwrap_or_default();
+                    record.diagnostics = parse_diagnostics(&String::from_utf8_lossy(&stderr));
+                    record.diagnostics.extend(parse_diagnostics(&String::from_utf8_lossy(&stdout)));
+                }
+                record.stdout_log = Some(stdout_log.to_string_lossy().to_string());
+                record.stderr_log = Some(stderr_log.to_string_lossy().to_string());
+            }
+            Err(err) => record.error = Some(err.to_string()),
+        }
+        record.duration_secs = started.elapsed().as_secs_f64();
+
+        if !record.success && record.probe {
+            log::warn!("{} in {}", record.summary(), directory);
+        } else if !record.success {
+            log::error!("{} in {}", record.summary(), directory);
+        }
+        let success = record.success;
+        self.records.borrow_mut().push(record);
+        success
+    }
+
+    // Spawn with output going straight to the log files and wait, killing the process group
+    // if it runs past the timeout. Returns the exit status and whether it timed out.
+    fn execute(&self, program: &str, args: &[&str], directory: &str, install: bool, stdout_log: &Path, stderr_log: &Path) -> std::io::Result<(ExitStatus, bool)> {
+        fs::create_dir_all(&self.log_directory)?;
+        let mut command = self.sandbox.command(program, args, directory, install, &self.envs);
+        command
+            .stdin(Stdio::null())
+            .stdout(fs::File::create(stdout_log)?)
+            .stderr(fs::File::create(stderr_log)?);
+        let cgroup = self.limits.apply(&mut command);
+
+        let mut child = command.spawn()?;
+        let deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Ok((status, false));
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                log::error!("Timed out after {:?}, killing process group {}", self.limits.timeout.unwrap_or_default(), child.id());
+                kill_process_group(child.id());
+                if let Some(cgroup) = &cgroup {
+                    cgroup.kill();
+                }
+                return Ok((child.wait()?, true));
+            }
+            thread::sleep(Duration::from_millis(200));
+        }
+    }
+
+    // The most recent command, which for a builder is the compile step.
+    pub fn last_command(&self) -> Option<String> {
+        self.records.borrow().last().map(|record| record.command.clone())
+    }
+
+    pub fn records(&self) -> Vec<CommandRecord> {
+        self.records.borrow().clone()
+    }
+
+    // Why the build failed, taken from the last install or compile step that didn't succeed.
+    // Earlier failures are often attempts a fallback got past. A failed probe is only used
+    // when nothing else failed.
+    pub fn failure_summary(&self) -> Option<String> {
+        let records = self.records.borrow();
+        let failed = || records.iter().rev().filter(|record| !record.success);
+        failed().find(|record| !record.probe).or_else(|| failed().next()).map(|record| record.summary())
+    }
+
+    fn log_path(&self, index: usize, program: &str, stream: &str) -> PathBuf {
+        self.log_directory.join(format!("{:02}-{}.{}.log", index, program, stream))
+    }
+}
+
+impl Drop for Runner {
+    fn drop(&mut self) {
+        self.sandbox.remove_caches();
+    }
+}
+
+// Whether program can be found on PATH, so builders don't attempt package managers that
+// aren't installed.
+pub fn on_path(program: &str) -> bool {
+    env::var_os("PATH").is_some_and(|paths| env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+}
+
+// Pull errors out of solc, forge, hardhat, truffle and package manager output. Handles
+// both the "Error: msg\n --> file:line:col:" layout and the older "file:line:col: TypeError: msg".
+pub fn parse_diagnostics(output: &str) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let mut seen_npm_error = false;
+
+    for line in output.lines() {
+        let trimmed = line.trim();
+        if let Some(location) = trimmed.strip_prefix("--> ") {
+            if let Some(last) = diagnostics.last_mut() {
+                if last.file.is_none() {
+                    let (file, line, column) = parse_location(location);
+                    last.file = file;
+                    last.line = line;
+                    last.column = column;
+                }
+            }
+        } else if let Some(message) = trimmed.strip_prefix("npm ERR! ") {
+            // npm repeats itself over many lines, the first one is the useful one.
+            if !seen_npm_error {
+                seen_npm_error = true;
+                diagnostics.push(Diagnostic { message: message.to_string(), file: None, line: None, column: None });
+            }
+        } else if trimmed.starts_with("error ") {
+            // yarn prefixes its errors with a bare "error"
+            diagnostics.push(Diagnostic { message: trimmed.to_string(), file: None, line: None, column: None });
+        } else if let Some(diagnostic) = located_error(trimmed) {
+            diagnostics.push(diagnostic);
+        } else if is_error(trimmed) {
+            diagnostics.push(Diagnostic { message: trimmed.to_string(), file: None, line: None, column: None });
+        }
+    }
+    diagnostics
+}
+
+// file:line:col: TypeError: msg
+fn located_error(line: &str) -> Option<Diagnostic> {
+    let (location, message) = line.split_once(": ")?;
+    let (file, line, column) = parse_location(location);
+    if line.is_some() && is_error(message) {
+        Some(Diagnostic { message: message.to_string(), file, line, column })
+    } else {
+        None
+    }
+}
+
+fn is_error(message: &str) -> bool {
+    let kind = message.split(':').next().unwrap_or_default();
+    kind.starts_with("Error") || (kind.ends_with("Error") && !kind.contains(' '))
+}
+
+// file:line:col with an optional trailing colon.
+fn parse_location(location: &str) -> (Option<String>, Option<u32>, Option<u32>) {
+    let location = location.trim().trim_end_matches(':');
+    let mut parts = location.rsplitn(3, ':');
+    let column = parts.next().and_then(|c| c.parse().ok());
+    let line = parts.next().and_then(|l| l.parse().ok());
+    match (parts.next(), line, column) {
+        (Some(file), Some(line), Some(column)) => (Some(file.to_string()), Some(line), Some(column)),
+        _ => (None, None, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn located(diagnostic: &Diagnostic) -> (Option<&str>, Option<u32>, Option<u32>) {
+        (diagnostic.file.as_deref(), diagnostic.line, diagnostic.column)
+    }
+
+    #[test]
+    fn solc_errors_take_the_next_location() {
+        let output = "Compiling 3 files with 0.8.19\nError (7576): Undeclared identifier.\n  --> src/Token.sol:12:9:\n   |\n12 |         mint();\n   |         ^^^^\n\nParserError: Expected ';' but got '}'\n --> src/Vault.sol:40:1:\n";
+        let diagnostics = parse_diagnostics(output);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].message, "Error (7576): Undeclared identifier.");
+        assert_eq!(located(&diagnostics[0]), (Some("src/Token.sol"), Some(12), Some(9)));
+        assert_eq!(diagnostics[1].message, "ParserError: Expected ';' but got '}'");
+        assert_eq!(located(&diagnostics[1]), (Some("src/Vault.sol"), Some(40), Some(1)));
+    }
+
+    #[test]
+    fn located_errors() {
+        let diagnostics = parse_diagnostics("contracts/Token.sol:7:5: TypeError: Member \"mint\" not found\n");
+        assert_eq!(diagnostics[0].message, "TypeError: Member \"mint\" not found");
+        assert_eq!(located(&diagnostics[0]), (Some("contracts/Token.sol"), Some(7), Some(5)));
+    }
+
+    #[test]
+    fn package_manager_errors() {
+        // Only npm's first line is kept.
+        let npm = parse_diagnostics("npm ERR! code ERESOLVE\nnpm ERR! ERESOLVE unable to resolve dependency tree\n");
+        assert_eq!(npm.len(), 1);
+        assert_eq!(npm[0].message, "code ERESOLVE");
+        assert_eq!(located(&npm[0]), (None, None, None));
+
+        let yarn = parse_diagnostics("info No lockfile found.\nerror Couldn't find package \"@openzeppelin/contracts@9.0.0\" on the \"npm\" registry.\n");
+        assert_eq!(yarn.len(), 1);
+        assert!(yarn[0].message.starts_with("error Couldn't find package"));
+    }
+
+    #[test]
+    fn warnings_and_progress_are_not_errors() {
+        let output = "Compiling 12 files with 0.8.19\nsrc/Token.sol:3:1: Warning: SPDX license identifier not provided\nWarning: Unused local variable.\n --> src/Token.sol:20:9:\nerrors: 0\nNo TypeError in output\nCompiler run successful with warnings\n";
+        assert!(parse_diagnostics(output).is_empty());
+    }
+}
@@ -0,0 +1,224 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use clap::ValueEnum;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum SandboxMode {
+    // Run builder commands directly as our user.
+    #[default]
+    None,
+    // Run builder commands under bubblewrap with a read-only view of the system.
+    Bwrap,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum NetworkPolicy {
+    #[default]
+    Allow,
+    // Only package manager installs may reach the network.
+    Install,
+    None,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ScriptPolicy {
+    #[default]
+    Allow,
+    // Pass --ignore-scripts to npm, yarn, pnpm and bun installs.
+    Deny,
+}
+
+// Package managers that run lifecycle scripts from dependencies on install.
+const SCRIPT_RUNNERS: [&str; 4] = ["npm", "yarn", "pnpm", "bun"];
+
+// Files in the home directory that hold credentials. Hidden inside the sandbox.
+const SECRET_DIRECTORIES: [&str; 7] = [".ssh", ".aws", ".gnupg", ".docker", ".kube", ".config/gh", ".config/gcloud"];
+const SECRET_FILES: [&str; 4] = [".npmrc", ".netrc", ".git-credentials", ".yarnrc.yml"];
+
+// Compilers and package manager binaries. Read-only, since whatever an install script left
+// there would run outside the sandbox later. Compilers have to be installed beforehand.
+const TOOLCHAIN_DIRECTORIES: [&str; 5] = [".foundry", ".svm", ".bun", ".local/share/pnpm", ".cache/hardhat-nodejs"];
+
+// Package caches installs write to. Each build gets empty ones of its own in place of these.
+const PACKAGE_CACHES: [&str; 5] = [".npm", ".yarn", ".cache", ".bun/install/cache", ".local/share/pnpm/store"];
+
+// The only environment variables passed into the sandbox, besides those the runner sets.
+const PASSED_ENV: [&str; 11] = ["PATH", "HOME", "USER", "LANG", "TERM", "HTTP_PROXY", "HTTPS_PROXY", "NO_PROXY", "http_proxy", "https_proxy", "no_proxy"];
+
+static CACHE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Clone, Debug, Default)]
+pub struct Sandbox {
+    pub mode: SandboxMode,
+    pub network: NetworkPolicy,
+    pub scripts: ScriptPolicy,
+    // The repository being built. The only part of the filesystem outside the caches that
+    // commands may write to.
+    pub writable: Option<PathBuf>,
+    // Where this build's package caches are kept, see remove_caches.
+    pub caches: Option<PathBuf>,
+}
+
+impl Sandbox {
+    pub fn for_directory(&self, directory: &str) -> Self {
+        let writable = Path::new(directory).canonicalize().unwrap_or_else(|_| PathBuf::from(directory));
+        let index = CACHE_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let caches = env::temp_dir().join(format!("rustarena-caches-{}-{}", std::process::id(), index));
+        Self { writable: Some(writable), caches: Some(caches), ..self.clone() }
+    }
+
+    // Delete the build's package caches once it's done with them.
+    pub fn remove_caches(&self) {
+        if let Some(caches) = self.caches.as_ref().filter(|caches| caches.exists()) {
+            if let Err(err) = fs::remove_dir_all(caches) {
+                log::warn!("Error removing package caches {}: {}", caches.to_string_lossy(), err);
+            }
+        }
+    }
+
+    pub fn scripts_allowed(&self) -> bool {
+        self.scripts == ScriptPolicy::Allow
+    }
+
+    // Extra arguments for an install command, --ignore-scripts when scripts are denied. Yarn
+    // Berry has no --ignore-scripts, its builder passes --mode=skip-build itself.
+    pub fn install_args(&self, program: &str, args: &[&str]) -> Vec<&'static str> {
+        if !self.scripts_allowed() && SCRIPT_RUNNERS.contains(&program) && !args.contains(&"--mode=skip-build") {
+            vec!["--ignore-scripts"]
+        } else {
+            Vec::new()
+        }
+    }
+
+    // The command to spawn for program with envs set, wrapped in bwrap when sandboxing.
+    pub fn command(&self, program: &str, args: &[&str], directory: &str, install: bool, envs: &[(String, String)]) -> Command {
+        match self.mode {
+            SandboxMode::None => {
+                let mut command = Command::new(program);
+                command.args(args).current_dir(directory).envs(envs.iter().map(|(key, value)| (key, value)));
+                command
+            }
+            SandboxMode::Bwrap => {
+                let home = env::var_os("HOME").map(PathBuf::from);
+                let mut command = Command::new("bwrap");
+                command.args(self.bwrap_args(home.as_deref(), directory, install, envs)).arg("--").arg(program).args(args);
+                command
+            }
+        }
+    }
+
+    fn bwrap_args(&self, home: Option<&Path>, directory: &str, install: bool, envs: &[(String, String)]) -> Vec<String> {
+        let mut args: Vec<String> = ["--ro-bind", "/", "/", "--dev", "/dev", "--proc", "/proc", "--tmpfs", "/tmp"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+
+        if let Some(home) = home {
+            for secret in SECRET_DIRECTORIES.iter().map(|d| home.join(d)).filter(|d| d.is_dir()) {
+                args.extend(["--tmpfs".to_string(), secret.to_string_lossy().to_string()]);
+            }
+            for secret in SECRET_FILES.iter().map(|f| home.join(f)).filter(|f| f.is_file()) {
+                args.extend(["--ro-bind".to_string(), "/dev/null".to_string(), secret.to_string_lossy().to_string()]);
+            }
+
+            // Parents are mounted before what's inside them, so hardhat's compilers stay
+            // read-only within the build's own .cache and the bun and pnpm stores writable.
+            let mut mounts: Vec<(&str, Option<PathBuf>)> = TOOLCHAIN_DIRECTORIES.iter().map(|directory| (*directory, None)).collect();
+            if let Some(caches) = &self.caches {
+                mounts.extend(PACKAGE_CACHES.iter().map(|cache| (*cache, Some(caches.join(cache.trim_start_matches('.').replace('/', "-"))))));
+            }
+            mounts.sort_by_key(|(directory, _)| directory.matches('/').count());
+            for (directory, cache) in mounts {
+                let target = home.join(directory);
+                if !target.is_dir() {
+                    continue;
+                }
+                let target = target.to_string_lossy().to_string();
+                match cache {
+                    Some(cache) => {
+                        if let Err(err) = fs::create_dir_all(&cache) {
+                            log::warn!("Error creating package cache {}: {}", cache.to_string_lossy(), err);
+                            continue;
+                        }
+                        args.extend(["--bind".to_string(), cache.to_string_lossy().to_string(), target]);
+                    }
+                    None => args.extend(["--ro-bind".to_string(), target.clone(), target]),
+                }
+            }
+        }
+
+        if let Some(writable) = &self.writable {
+            let writable = writable.to_string_lossy().to_string();
+            args.extend(["--bind".to_string(), writable.clone(), writable]);
+        }
+
+        let directory = Path::new(directory).canonicalize().unwrap_or_else(|_| PathBuf::from(directory));
+        args.extend(["--chdir".to_string(), directory.to_string_lossy().to_string()]);
+
+        // Tokens and cloud credentials in our environment don't reach install scripts.
+        args.push("--clearenv".to_string());
+        for key in PASSED_ENV {
+            if let Some(value) = env::var_os(key) {
+                args.extend(["--setenv".to_string(), key.to_string(), value.to_string_lossy().to_string()]);
+            }
+        }
+        for (key, value) in envs {
+            args.extend(["--setenv".to_string(), key.clone(), value.clone()]);
+        }
+
+        // A new pid namespace means killing bwrap takes every process in the sandbox with it.
+        args.extend(["--unshare-all", "--die-with-parent", "--new-session"].iter().map(|arg| arg.to_string()));
+        let network = match self.network {
+            NetworkPolicy::Allow => true,
+            NetworkPolicy::Install => install,
+            NetworkPolicy::None => false,
+        };
+        if network {
+            args.push("--share-net".to_string());
+        }
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mounts<'a>(args: &'a [String], flag: &str) -> Vec<&'a str> {
+        args.windows(3).filter(|window| window[0] == flag).map(|window| window[2].as_str()).collect()
+    }
+
+    #[test]
+    fn toolchains_are_read_only_and_caches_per_build() {
+        let home = env::temp_dir().join(format!("rustarena-sandbox-{}", std::process::id()));
+        for directory in [".foundry/bin", ".npm", ".cache/hardhat-nodejs", ".ssh"] {
+            fs::create_dir_all(home.join(directory)).unwrap();
+        }
+        let sandbox = Sandbox { mode: SandboxMode::Bwrap, ..Sandbox::default() }.for_directory("/tmp/repo");
+        let args = sandbox.bwrap_args(Some(&home), "/tmp/repo", true, &[("FOUNDRY_PROFILE".to_string(), "ci".to_string())]);
+        let path = |directory: &str| home.join(directory).to_string_lossy().to_string();
+
+        let read_only = mounts(&args, "--ro-bind");
+        assert!(read_only.contains(&path(".foundry").as_str()));
+        // Mounted after the build's own .cache, or that would hide it.
+        let writable = mounts(&args, "--bind");
+        assert_eq!(writable, [path(".npm").as_str(), path(".cache").as_str(), "/tmp/repo"]);
+        let position = |target: &str| args.iter().position(|arg| arg == target).unwrap();
+        assert!(position(&path(".cache")) < position(&path(".cache/hardhat-nodejs")));
+        let caches = sandbox.caches.as_ref().unwrap();
+        assert!(args.contains(&caches.join("npm").to_string_lossy().to_string()));
+
+        assert!(args.contains(&"--clearenv".to_string()));
+        assert!(args.windows(3).any(|window| window == ["--setenv", "FOUNDRY_PROFILE", "ci"]));
+        // Nothing else from our environment, GITHUB_TOKEN included, gets in.
+        let keys = args.windows(2).filter(|window| window[0] == "--setenv").map(|window| window[1].as_str());
+        assert!(keys.into_iter().all(|key| key == "FOUNDRY_PROFILE" || PASSED_ENV.contains(&key)));
+
+        sandbox.remove_caches();
+        assert!(!caches.exists());
+        fs::remove_dir_all(&home).unwrap();
+    }
+}
@@ -1,6 +1,5 @@
-use std::process::{Command, exit};
 use log;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::fs;
 use std::env;
 use std::collections::HashMap;
@@ -8,7 +7,8 @@ use walkdir::WalkDir;
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
 
-use crate::builders::build::Build;
+use crate::builders::build::{Build, BuildOutput, ProjectConfig};
+use crate::builders::runner::Runner;
 use crate::contract::{Contract, Kind};
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -73,29 +73,73 @@ pub struct Documentation {
 }
 pub struct TruffleBuilder;
 
-impl Build for TruffleBuilder {
-    fn build(&self, directory: &str) -> Result<(String, Vec<Contract>), Box<dyn std::error::Error>> {
-        log::info!("Executing npm install in {}", directory);
-        let install_result = Command::new("npm")
-            .arg("install")
-            .current_dir(directory)
-            .output();
-    
-        if let Err(err) = install_result {
-            log::error!("Error executing 'npm install': {}", err);
-            exit(1);
+// Config file names, current first then the legacy name used before Truffle 5.
+pub const CONFIG_FILES: [&str; 2] = ["truffle-config.js", "truffle.js"];
+
+// Loads the config with node and prints the settings we need. Relative directories are
+// resolved against the project like truffle does. Written into the project like the hardhat
+// config script so it can be found from inside the sandbox.
+const CONFIG_SCRIPT: &str = r#"
+const path = require("path");
+const config = require(path.resolve(process.argv[2]));
+const resolve = (directory) => (directory ? path.resolve(directory) : null);
+const solc = ((config.compilers || {}).solc || {}).version || null;
+console.log(CONFIG_MARKER + JSON.stringify({
+  contracts_build_directory: resolve(config.contracts_build_directory),
+  contracts_directory: resolve(config.contracts_directory),
+  solc,
+}));
+"#;
+const CONFIG_SCRIPT_FILE: &str = ".rustarena-truffle-config.js";
+const CONFIG_MARKER: &str = "RUSTARENA_CONFIG ";
+
+#[derive(Debug, Deserialize)]
+pub struct TruffleConfig {
+    pub contracts_build_directory: Option<String>,
+    pub contracts_directory: Option<String>,
+    pub solc: Option<String>,
+}
+
+// The truffle config in directory, if there is one.
+pub fn config_file(directory: &Path) -> Option<PathBuf> {
+    CONFIG_FILES.iter().map(|file| directory.join(file)).find(|file| file.exists())
+}
+
+impl TruffleBuilder {
+    // Evaluate the config. Needs dependencies installed as configs often require providers.
+    pub fn resolve_config(&self, directory: &str, runner: &Runner) -> Option<TruffleConfig> {
+        let config_file = config_file(Path::new(directory))?;
+        let file_name = config_file.file_name()?.to_string_lossy().to_string();
+        let script = Path::new(directory).join(CONFIG_SCRIPT_FILE);
+        let contents = format!("const CONFIG_MARKER = {:?};{}", CONFIG_MARKER, CONFIG_SCRIPT);
+        if let Err(err) = fs::write(&script, contents) {
+            log::error!("Error writing {}: {}", script.to_string_lossy(), err);
+            return None;
         }
+        let output = runner.output("node", &[CONFIG_SCRIPT_FILE, &file_name], directory);
+        fs::remove_file(&script).ok();
+
+        let output = output?;
+        let line = output.lines().find_map(|line| line.strip_prefix(CONFIG_MARKER))?;
+        match serde_json::from_str(line) {
+            Ok(config) => Some(config),
+            Err(err) => {
+                log::error!("Error parsing the truffle config for {}: {}", directory, err);
+                None
+            }
+        }
+    }
+}
+
+impl Build for TruffleBuilder {
+    fn build(&self, directory: &str, runner: &Runner) -> Result<BuildOutput, Box<dyn std::error::Error>> {
+        runner.install("npm", &["install"], directory);
     
-        // Execute `forge build` in the repository directory
-        log::info!("Executing truffle compile in {}", directory);
-        let build_result = Command::new("truffle")
-            .arg("compile")
-            .current_dir(directory)
-            .output();
-    
-        if let Err(err) = build_result {
-            log::error!("Error executing 'truffle compile': {}", err);
-            exit(1);
+        let resolved = self.resolve_config(directory, runner);
+
+        // Execute `truffle compile` in the repository directory
+        if !runner.run("truffle", &["compile"], directory) {
+            log::error!("truffle compile failed in {}, collecting whatever was built", directory);
         }
     
         if let Ok(current_dir) = env::current_dir() {
@@ -104,39 +148,67 @@ impl Build for TruffleBuilder {
             log::error!("Failed to get current directory");
         }
 
-        let mut artifact_dir = "";
-
-        let src_dir = Path::new(&directory).join("src");
-        if !src_dir.exists() || !src_dir.is_dir() {
-            log::error!("Error: 'src' directory not found in {}", directory);
-            //exit(1);
-        } else {
-            artifact_dir = "src";
-        }
-
-        // Check if "out" directory exists
-        let build_dir = Path::new(&directory).join("build");
+        // contracts_build_directory defaults to build/contracts.
+        let build_dir = match resolved.as_ref().and_then(|config| config.contracts_build_directory.as_ref()) {
+            Some(build_dir) => PathBuf::from(build_dir),
+            None => Path::new(&directory).join("build/contracts"),
+        };
         log::info!("Checking for the build directory {}", build_dir.to_string_lossy());
         if !build_dir.exists() {
-            log::error!("Error: 'out' directory {} not found in {}", build_dir.to_string_lossy(), directory);
+            log::error!("Error: build directory {} not found in {}", build_dir.to_string_lossy(), directory);
             //exit(1);
-        } else {
-            artifact_dir = "build";
         }
 
-        let result = process_truffle_directory(directory, artifact_dir);
-        Ok(result)
+        let result = process_truffle_directory(directory, &build_dir);
+        let config = resolved.map(|config| ProjectConfig {
+            artifacts: build_dir.to_string_lossy().to_string(),
+            sources: config.contracts_directory.into_iter().collect(),
+            compilers: config.solc.into_iter().collect(),
+            ..Default::default()
+        });
+        Ok(BuildOutput::new(result, config))
+    }
+}
+
+// Truffle artifacts are JSON with contractName, bytecode, sourcePath and an ast. Anything else
+// in the build directory (network files, package.json, other tools' output) is skipped.
+pub fn read_artifact(path: &Path) -> Option<Metadata> {
+    let json_content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) => {
+            log::error!("Error reading JSON file '{}': {}", path.display(), err);
+            return None;
+        }
+    };
+    let value: serde_json::Value = match serde_json::from_str(&json_content) {
+        Ok(value) => value,
+        Err(err) => {
+            log::error!("Error parsing JSON file '{}': {}", path.display(), err);
+            return None;
+        }
+    };
+    let is_artifact = ["contractName", "bytecode", "sourcePath"].iter().all(|key| value.get(key).is_some_and(|v| v.is_string()))
+        && value.get("ast").is_some_and(|ast| ast.is_object());
+    if !is_artifact {
+        log::debug!("Skipping {}, not a truffle artifact", path.display());
+        return None;
+    }
+    match serde_json::from_value(value) {
+        Ok(metadata) => Some(metadata),
+        Err(err) => {
+            log::error!("Error parsing JSON file '{}': {}", path.display(), err);
+            None
+        }
     }
 }
 
-pub fn process_truffle_directory(repo_directory: &str, artifact_dir: &str) -> (String, Vec<Contract>) {
-    let out_dir = Path::new(&repo_directory).join(artifact_dir);
+pub fn process_truffle_directory(repo_directory: &str, out_dir: &Path) -> (String, Vec<Contract>) {
     log::info!("Looking for built contracts in {}", &out_dir.to_string_lossy());
 
     // Contract map stores a mapping from contract name to Contract.
     let mut contract_map: HashMap<String, Contract> = HashMap::new();
 
-    let walker = WalkDir::new(&out_dir).into_iter();
+    let walker = WalkDir::new(out_dir).into_iter();
 
     // First pass will find all json files, parse them and add them to a contract_map
     // Imports are None at this stage as they are populated in the second pass. 
@@ -149,20 +221,9 @@ pub fn process_truffle_directory(repo_directory: &str, artifact_dir: &str) -> (S
                 if extension == "json" {
                     if let Some(file_stem) = entry_path.file_stem() {
                         if let Some(contract_name) = file_stem.to_str() {
-                            let json_content = match fs::read_to_string(&entry_path) {
-                                Ok(content) => content,
-                                Err(err) => {
-                                    log::error!("Error reading JSON file '{}': {}", entry_path.display(), err);
-                                    continue;
-                                }
-                            };
-
-                            let metadata: Metadata = match serde_json::from_str(&json_content) {
-                                Ok(metadata) => metadata,
-                                Err(err) => {
-                                    log::error!("Error parsing JSON file '{}': {}", entry_path.display(), err);
-                                    continue;
-                                }
+                            let metadata = match read_artifact(entry_path) {
+                                Some(metadata) => metadata,
+                                None => continue,
                             };
 
                             let bytecode_object = metadata.bytecode;
@@ -192,11 +253,13 @@ pub fn process_truffle_directory(repo_directory: &str, artifact_dir: &str) -> (S
                             
                             let file_contents_path = Path::new(&repo_directory).join(&absolute_path_stripped);
                             log::debug!("Trying to read file in {}", &file_contents_path.to_string_lossy());
-                            let file_contents = match std::fs::read_to_string(file_contents_path) {
-                                Ok(contents) => contents,
+                            // Fall back to the source embedded in the artifact, reported as missing if that's empty too.
+                            let file_contents = match std::fs::read_to_string(&file_contents_path) {
+                                Ok(contents) => Some(contents),
+                                Err(_) if !metadata.source.is_empty() => Some(metadata.source.clone()),
                                 Err(err) => {
-                                    log::error!("Error trying to read file: {}", err);
-                                    continue;
+                                    log::warn!("Unable to read the source of {} at {}: {}", contract_name, file_contents_path.to_string_lossy(), err);
+                                    None
                                 }
                             };
                             
@@ -210,7 +273,8 @@ pub fn process_truffle_directory(repo_directory: &str, artifact_dir: &str) -> (S
                                 deployed_sourcemap: Some(metadata.deployed_source_map),
                                 absolute_path: Some(absolute_path_stripped.to_string()),
                                 id: Some(metadata.ast.id.try_into().unwrap()),
-                                file_contents: Some(file_contents), 
+                                file_contents,
+                                source_hash: None,
                             };
                             contract_map.insert(contract_name.to_owned(), contract);
                         }
@@ -225,7 +289,7 @@ pub fn process_truffle_directory(repo_directory: &str, artifact_dir: &str) -> (S
     // We append the imports to the contract's imports field.
 
     let contract_map_clone = contract_map.clone();
-    let walker = WalkDir::new(&out_dir).into_iter();
+    let walker = WalkDir::new(out_dir).into_iter();
 
     for entry in walker.flatten() {
         let entry_path = entry.path();
@@ -233,20 +297,9 @@ pub fn process_truffle_directory(repo_directory: &str, artifact_dir: &str) -> (S
         if entry_path.is_file() && entry_path.extension() == Some("json".as_ref()) {
             if let Some(file_stem) = entry_path.file_stem() {
                 if let Some(contract_name) = file_stem.to_str() {
-                    let json_content = match fs::read_to_string(&entry_path) {
-                        Ok(content) => content,
-                        Err(err) => {
-                            log::error!("Error reading JSON file '{}': {}", entry_path.display(), err);
-                            continue;
-                        }
-                    };
-
-                    let metadata: Metadata = match serde_json::from_str(&json_content) {
-                        Ok(metadata) => metadata,
-                        Err(parseerr) => {
-                            log::error!("Error parsing JSON file '{}': {}", entry_path.display(), parseerr);
-                            continue;
-                        }
+                    let metadata = match read_artifact(entry_path) {
+                        Some(metadata) => metadata,
+                        None => continue,
                     };
 
                     if let Some(contract) = contract_map.get_mut(contract_name) {
@@ -1,33 +1,266 @@
-use std::path::Path;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use std::fs;
-use tokio::task::{spawn_blocking,spawn};
+use tokio::task::{block_in_place, spawn_blocking, spawn, JoinError};
+use tokio::time::sleep;
 use std::sync::Arc;
 use futures::future::try_join_all;
 use tokio::sync::Semaphore;
-use clap::Parser;
-use log;
+use chrono::{DateTime, Utc};
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
 
-use crate::parsers::code4rena::Code4renaParser;
-use crate::parsers::sherlock::SherlockParser;
-use crate::parsers::immunefi::ImmunefiParser;
-use crate::parsers::hats::HatsParser;
-use crate::github_api;
-use crate::contract::{process_repository, Kind};
-use crate::parsers::parse::Repo;
+use rustarena::parsers::code4rena::Code4renaParser;
+use rustarena::parsers::sherlock::SherlockParser;
+use rustarena::parsers::immunefi::ImmunefiParser;
+use rustarena::parsers::hats::HatsParser;
+use rustarena::diff::{self, DiffFormat};
+use rustarena::report::{self, ReportFormat};
+use crate::doctor;
+use rustarena::github_api;
+use rustarena::source_host::{self, SourceHost};
+use rustarena::builders::detect::BuilderKind;
+use rustarena::builders::limits::Limits;
+use rustarena::builders::sandbox::{NetworkPolicy, Sandbox, SandboxMode, ScriptPolicy};
+use rustarena::contract::{process_repository, BuildOptions};
+use rustarena::output::manifest::{update_manifest, write_manifest, RepoOutcome, RepoReport};
+use rustarena::output::schema::{result_schema, ResultEnvelope};
+use rustarena::output::sink::{open_sink, sort_contracts, OutputFormat, OutputSink};
+use rustarena::parsers::parse::Repo;
+use rustarena::notify::Notifier;
+use rustarena::serve::{self, BuildFn};
+use rustarena::watch::{emit_events, EventKind, WatchState};
+use rustarena::quarantine::{QuarantineAction, QuarantinePolicy};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    #[arg(short, long)]
-    github: Option<String>,
+    #[command(subcommand)]
+    command: Command,
 
-    #[arg(short, long, default_value = "30")]
-    max_builders: usize,
+    /// Extra self-hosted git instance to recognise in links, as kind=host (e.g. gitea=git.example.org)
+    #[arg(long = "git-host", global = true, value_parser = source_host::parse_host_arg)]
+    git_hosts: Vec<(String, SourceHost)>,
+}
 
+// How repositories are built and where their results go, shared by the commands that build.
+#[derive(clap::Args, Debug)]
+struct BuildArgs {
+    /// Move unsupported repositories aside instead of deleting them. Shorthand for --on-unsupported move
     #[arg(short, long, default_value = "false")]
     keep_unsupported: bool,
+
+    /// What to do with repositories whose build produced no contracts
+    #[arg(long, value_enum, default_value = "move")]
+    on_failure: QuarantineAction,
+
+    /// What to do with repositories no builder supports
+    #[arg(long, value_enum, default_value = "delete")]
+    on_unsupported: QuarantineAction,
+
+    /// Seconds a single builder command may run before it is killed, 0 for no limit
+    #[arg(long, default_value = "1800")]
+    build_timeout: u64,
+
+    /// Memory limit in megabytes for each builder command
+    #[arg(long)]
+    memory_limit: Option<u64>,
+
+    /// CPU time limit in seconds for each builder command
+    #[arg(long)]
+    cpu_limit: Option<u64>,
+
+    /// cgroup v2 directory to run each builder command in a child cgroup of
+    #[arg(long)]
+    cgroup: Option<PathBuf>,
+
+    /// Isolate builder commands. bwrap gives them a read-only system with only the repository writable
+    #[arg(long, value_enum, default_value = "none")]
+    sandbox: SandboxMode,
+
+    /// Network access for sandboxed commands, install allows it for dependency installs only
+    #[arg(long, value_enum, default_value = "allow")]
+    sandbox_network: NetworkPolicy,
+
+    /// Whether npm/yarn/pnpm/bun installs may run dependency lifecycle scripts
+    #[arg(long, value_enum, default_value = "allow")]
+    install_scripts: ScriptPolicy,
+
+    /// Foundry profile to build with, sets FOUNDRY_PROFILE for builder commands
+    #[arg(long)]
+    forge_profile: Option<String>,
+
+    /// Only use artifacts committed to the repository (out/, artifacts/, build/contracts), never compile
+    #[arg(long)]
+    artifacts_only: bool,
+
+    /// Build with this builder instead of detecting one
+    #[arg(long, value_enum)]
+    builder: Option<BuilderKind>,
+
+    /// Builders to try, in order, when the detected ones produce no contracts
+    #[arg(long, value_enum, value_delimiter = ',', num_args = 0.., default_value = "forge")]
+    fallback: Vec<BuilderKind>,
+
+    /// Where to write built contracts, any of json, jsonl, sqlite and files, comma separated
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "json")]
+    output_format: Vec<OutputFormat>,
+
+    /// Directory to write results, logs, failure records and the manifest to
+    #[arg(long, default_value = "results")]
+    results_dir: PathBuf,
 }
 
+// The bounty platforms repositories are discovered on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum ParserKind {
+    Immunefi,
+    Code4rena,
+    Sherlock,
+    Hats,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run the parsers and list the repositories they find, without cloning or building anything
+    Discover {
+        /// Parsers to run, comma separated. All of them by default
+        #[arg(long, value_enum, value_delimiter = ',')]
+        parser: Vec<ParserKind>,
+        /// Write the repositories to this file instead of stdout, to build later with build --list
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Build the given repositories, checkouts and archives
+    #[command(group(ArgGroup::new("targets").required(true).multiple(true)))]
+    Build {
+        /// Repository link to clone and build
+        #[arg(short, long, alias = "github", group = "targets")]
+        url: Vec<String>,
+        /// Existing checkout to build in place without cloning, moving or deleting it
+        #[arg(long, group = "targets")]
+        path: Vec<String>,
+        /// Local zip or tarball to build instead of cloning
+        #[arg(long, group = "targets")]
+        archive: Vec<String>,
+        /// File of repositories to build, as written by discover --out or one link per line
+        #[arg(long, group = "targets")]
+        list: Option<PathBuf>,
+        #[arg(short, long, default_value = "30")]
+        max_builders: usize,
+        #[command(flatten)]
+        build: BuildArgs,
+    },
+    /// Discover repositories on every platform and build them all
+    Run {
+        /// Parsers to run, comma separated. All of them by default
+        #[arg(long, value_enum, value_delimiter = ',')]
+        parser: Vec<ParserKind>,
+        #[arg(short, long, default_value = "30")]
+        max_builders: usize,
+        #[command(flatten)]
+        build: BuildArgs,
+    },
+    /// Summarise a run from its manifest: outcomes, contract counts and why repositories failed
+    Report {
+        /// Results directory, or its manifest.json, of the run
+        #[arg(default_value = "results")]
+        results: PathBuf,
+        /// Only list repositories with these outcomes, comma separated
+        #[arg(long, value_enum, value_delimiter = ',')]
+        outcome: Vec<RepoOutcome>,
+        #[arg(long, value_enum, default_value = "text")]
+        format: ReportFormat,
+    },
+    /// Check the toolchains builders need, Chrome, the solc cache and disk space
+    Doctor,
+    /// Print the JSON Schema of the result files
+    Schema {
+        /// Write the schema to this file instead of stdout
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Compare two runs' results: new and removed repositories, changed commits and contracts
+    Diff {
+        /// Results directory, or its manifest.json, of the earlier run
+        from: PathBuf,
+        /// Results directory, or its manifest.json, of the later run
+        to: PathBuf,
+        #[arg(long, value_enum, default_value = "text")]
+        format: DiffFormat,
+    },
+    /// Discover repositories on a schedule and build only the new ones and those whose upstream commit changed
+    Watch {
+        /// Seconds between discovery cycles
+        #[arg(long, default_value = "3600")]
+        interval: u64,
+        /// Where the repositories seen by previous cycles are kept, watch-state.json in the results directory by default
+        #[arg(long)]
+        state: Option<PathBuf>,
+        /// Run a single cycle and exit, for running from cron
+        #[arg(long)]
+        once: bool,
+        /// JSON file of webhook and command hooks to notify of events
+        #[arg(long)]
+        notify: Option<PathBuf>,
+        #[arg(short, long, default_value = "30")]
+        max_builders: usize,
+        #[command(flatten)]
+        build: BuildArgs,
+    },
+    /// Serve the results over a read-only HTTP API, with an endpoint to queue builds
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: SocketAddr,
+        #[command(flatten)]
+        build: BuildArgs,
+    },
+}
+
+impl BuildArgs {
+    fn build_options(&self) -> BuildOptions {
+        let on_unsupported = if self.keep_unsupported { QuarantineAction::Move } else { self.on_unsupported };
+        BuildOptions {
+            quarantine: QuarantinePolicy::new(self.on_failure, on_unsupported),
+            limits: Limits {
+                timeout: (self.build_timeout > 0).then(|| Duration::from_secs(self.build_timeout)),
+                memory_bytes: self.memory_limit.map(|megabytes| megabytes * 1024 * 1024),
+                cpu_seconds: self.cpu_limit,
+                cgroup_parent: self.cgroup.clone(),
+            },
+            sandbox: Sandbox {
+                mode: self.sandbox,
+                network: self.sandbox_network,
+                scripts: self.install_scripts,
+                writable: None,
+                caches: None,
+            },
+            forge_profile: self.forge_profile.clone(),
+            artifacts_only: self.artifacts_only,
+            builder: self.builder,
+            fallback: self.fallback.clone(),
+            results_dir: self.results_dir.clone(),
+        }
+    }
+
+    // One sink per output format. Exits if any can't be opened, before anything is built.
+    fn sinks(&self) -> Sinks {
+        let mut formats = self.output_format.clone();
+        formats.sort();
+        formats.dedup();
+        match formats.into_iter().map(|format| open_sink(format, &self.results_dir)).collect() {
+            Ok(sinks) => Arc::new(sinks),
+            Err(err) => {
+                log::error!("Unable to open output: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+type Sinks = Arc<Vec<Box<dyn OutputSink>>>;
+
 pub struct Cli {
 }
 
@@ -40,115 +273,413 @@ impl Cli {
     pub async fn run(&self) {
         let args = Args::parse();
 
-        let mut tasks = Vec::new();
-
-        if let Some(github_link) = &args.github {
-            let repo_name = format!("repos/{}", github_api::get_last_path_part(&github_link.as_str()).unwrap());
-            // Process a single GitHub repository
-            let repo = Repo {
-                parser: "github_command_line".to_string(),
-                name: repo_name,
-                url: github_link.clone(),
-                commit: None,
-            };
-            log::debug!("Initiating Github build for {}", &repo.name);
-            spawn_blocking(move || {
-                if let Err(err) = process_results(&repo, args.keep_unsupported) {
-                    log::error!("Error processing repository: {}", err);
-                }
-            });
-        } else {
-            tasks.push(spawn({
-                let immunefi = Arc::new(ImmunefiParser::new());
-                async move {
-                    immunefi.parse_dom().await
-                }
-            }));
+        for (host, kind) in &args.git_hosts {
+            source_host::register_host(host, kind.clone());
+        }
 
-            tasks.push(spawn({
-                let code4rena = Arc::new(Code4renaParser::new());
-                async move {
-                    code4rena.parse_dom().await
+        match args.command {
+            Command::Discover { parser, out } => {
+                let discovery = discover(&parser).await;
+                log::info!("Discovered {} repositories", discovery.repos.len());
+                if let Err(err) = write_repos(&discovery.repos, out.as_deref()) {
+                    log::error!("{}", err);
+                    std::process::exit(1);
                 }
-            }));
+            }
+            Command::Build { url, path, archive, list, max_builders, build } => {
+                let run_started_at = Utc::now();
+                let options = Arc::new(build.build_options());
+                let sinks = build.sinks();
 
-            tasks.push(spawn({
-                let sherlock = Arc::new(SherlockParser::new());
-                async move {
-                    sherlock.parse_dom().await
+                let mut repos = Vec::new();
+                for link in &url {
+                    match github_api::repo_from_link(link, "github_command_line") {
+                        Some(repo) => repos.push(repo),
+                        None => log::error!("Unable to name a checkout for {}", link),
+                    }
                 }
-            }));
-
-            tasks.push(spawn({
-                let hats = Arc::new(HatsParser::new());
-                async move {
-                    hats.parse_dom().await
+                for archive in archive {
+                    // clone_repository unpacks it into repos/ like a clone.
+                    repos.push(Repo {
+                        parser: "archive_command_line".to_string(),
+                        name: format!("repos/{}", github_api::archive_stem(&archive).unwrap_or_else(|| "archive".to_string())),
+                        url: archive,
+                        commit: None,
+                    });
                 }
-            }));
-            
-            //Set the maximum number of concurrent builders.
-            let semaphore = Arc::new(Semaphore::new(args.max_builders));
-            
-            let builder_tasks = try_join_all(tasks)
-                .await
-                .unwrap()
-                .into_iter()
-                .flat_map(|result| result.unwrap())
-                .map(|repo| {
-                    let semaphore = Arc::clone(&semaphore);
-                    // Spawn a task for each repository
-                    spawn(async move {
-                        let permit = semaphore.acquire().await.expect("Failed to acquire semaphore permit");
-                        if let Err(err) = process_results(&repo, args.keep_unsupported) {
-                            log::error!("Error processing repository: {}", err);
+                if let Some(list) = &list {
+                    match read_repo_list(list) {
+                        Ok(listed) => repos.extend(listed),
+                        Err(err) => {
+                            log::error!("{}", err);
+                            std::process::exit(1);
                         }
-                        drop(permit);
-                    })
-                })
-                .collect::<Vec<_>>();
-            
-            try_join_all(builder_tasks).await.unwrap();
-        }
-        
-    }
-}
+                    }
+                }
+                let mut reports = build_all(repos, &options, &sinks, max_builders).await;
 
-fn process_results(repo: &Repo, keep_unsupported: bool) -> Result<(), Box<dyn std::error::Error>> {
-    match github_api::clone_repository(&repo) {
-        Ok(_) => {
-            match process_repository(&repo, keep_unsupported) {
-                Ok((_repo_name, contract_data)) => {
-                    if contract_data.len() > 0 {
-                        let mut sorted_contracts = contract_data;
-                        sorted_contracts.sort_by_key(|contract| match contract.kind {
-                            Kind::Interface => 0,
-                            Kind::Contract => 1,
-                        });
-    
-                        // Create a results directory if it doesn't exist. 
-                        let results_dir = Path::new("results");
-                        if !results_dir.exists() {
-                            fs::create_dir(results_dir)?;
+                // Local working copies are built one at a time, directly in place.
+                let options = Arc::new(BuildOptions { quarantine: QuarantinePolicy::in_place(), ..build.build_options() });
+                for path in path {
+                    let directory = match fs::canonicalize(&path) {
+                        Ok(directory) => directory.to_string_lossy().to_string(),
+                        Err(err) => {
+                            log::error!("Unable to read {}: {}", path, err);
+                            continue;
+                        }
+                    };
+                    let repo = Repo {
+                        parser: "path_command_line".to_string(),
+                        name: directory.clone(),
+                        url: directory,
+                        commit: None,
+                    };
+                    log::debug!("Initiating local build for {}", &repo.name);
+                    let options = Arc::clone(&options);
+                    let sinks = Arc::clone(&sinks);
+                    reports.push(spawn_blocking(move || build_results(&repo, &options, &sinks, Utc::now())).await);
+                }
+                finish_run(reports, run_started_at, &build.results_dir);
+            }
+            Command::Run { parser, max_builders, build } => {
+                let run_started_at = Utc::now();
+                let options = Arc::new(build.build_options());
+                let sinks = build.sinks();
+                let discovery = discover(&parser).await;
+                let reports = build_all(discovery.repos, &options, &sinks, max_builders).await;
+                finish_run(reports, run_started_at, &build.results_dir);
+            }
+            Command::Report { results, outcome, format } => {
+                if let Err(err) = report::report_run(&results, &outcome).and_then(|report| report::print_report(&report, format)) {
+                    log::error!("{}", err);
+                    std::process::exit(1);
+                }
+            }
+            Command::Doctor => {
+                if !doctor::run() {
+                    std::process::exit(1);
+                }
+            }
+            Command::Schema { out } => {
+                match out {
+                    Some(out) => {
+                        if let Err(err) = fs::write(&out, result_schema()) {
+                            log::error!("Unable to write {}: {}", out.to_string_lossy(), err);
+                            std::process::exit(1);
                         }
-    
-                        // Serialize and write the sorted contracts to a JSON file
-                        let repo_path = Path::new(&repo.name).strip_prefix("repos")?;
-                        let json_data = serde_json::to_string_pretty(&sorted_contracts)?;
-                        let json_filename = format!("results/{}_{}_contracts.json", &repo.parser, &repo_path.to_string_lossy());
-                        log::debug!("Writing {}", &json_filename);
-                        fs::write(json_filename, json_data)?;
-                    } else {
-                        log::error!("No contract output for {}", &repo.name);
                     }
+                    None => print!("{}", result_schema()),
                 }
-                Err(err) => {
-                    log::error!("Error processing repository: {}", err);
+            }
+            Command::Diff { from, to, format } => {
+                if let Err(err) = diff::diff_runs(&from, &to).and_then(|diff| diff::print_diff(&diff, format)) {
+                    log::error!("{}", err);
+                    std::process::exit(1);
+                }
+            }
+            Command::Watch { interval, state, once, notify, max_builders, build } => {
+                let options = Arc::new(build.build_options());
+                let sinks = build.sinks();
+                let notifier = match notify.as_deref().map(Notifier::load).transpose() {
+                    Ok(notifier) => Arc::new(notifier.unwrap_or_default()),
+                    Err(err) => {
+                        log::error!("{}", err);
+                        std::process::exit(1);
+                    }
+                };
+                let state = state.unwrap_or_else(|| build.results_dir.join("watch-state.json"));
+                watch(&state, interval, once, &options, &sinks, &notifier, max_builders).await;
+            }
+            Command::Serve { listen, build } => {
+                let options = Arc::new(build.build_options());
+                let sinks = build.sinks();
+                let results_dir = build.results_dir.clone();
+                let build: BuildFn = Arc::new(move |repo| process_results(&repo, &options, &sinks));
+                if let Err(err) = serve::run(listen, results_dir, build).await {
+                    log::error!("Unable to serve: {}", err);
+                    std::process::exit(1);
                 }
             }
         }
+    }
+}
+
+// Repositories listed by the parsers, and the parsers that failed.
+struct Discovery {
+    repos: Vec<Repo>,
+    failed_parsers: Vec<String>,
+}
+
+// Run the given parsers, or all of them when none are given.
+async fn discover(parsers: &[ParserKind]) -> Discovery {
+    let mut parsers = if parsers.is_empty() { ParserKind::value_variants().to_vec() } else { parsers.to_vec() };
+    parsers.sort();
+    parsers.dedup();
+
+    let mut tasks = Vec::new();
+    for kind in parsers {
+        tasks.push(match kind {
+            ParserKind::Immunefi => {
+                let parser = ImmunefiParser::new();
+                (parser.name.clone(), spawn(async move { parser.parse_dom().await }))
+            }
+            ParserKind::Code4rena => {
+                let parser = Code4renaParser::new();
+                (parser.name.clone(), spawn(async move { parser.parse_dom().await }))
+            }
+            ParserKind::Sherlock => {
+                let parser = SherlockParser::new();
+                (parser.name.clone(), spawn(async move { parser.parse_dom().await }))
+            }
+            ParserKind::Hats => {
+                let parser = HatsParser::new();
+                (parser.name.clone(), spawn(async move { parser.parse_dom().await }))
+            }
+        });
+    }
+
+    let mut discovery = Discovery { repos: Vec::new(), failed_parsers: Vec::new() };
+    for (name, task) in tasks {
+        match task.await {
+            Ok(Ok(repos)) => discovery.repos.extend(repos),
+            Ok(Err(err)) => {
+                log::error!("The {} parser failed: {}", name, err);
+                discovery.failed_parsers.push(name);
+            }
+            Err(err) => {
+                log::error!("The {} parser task failed: {}", name, err);
+                discovery.failed_parsers.push(name);
+            }
+        }
+    }
+    discovery
+}
+
+// Print discovered repositories as JSON, or save them for build --list.
+fn write_repos(repos: &[Repo], out: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
+    let json = serde_json::to_string_pretty(repos)?;
+    match out {
+        Some(out) => {
+            fs::write(out, json).map_err(|err| format!("Unable to write {}: {}", out.to_string_lossy(), err))?;
+            log::info!("Wrote {} repositories to {}", repos.len(), out.to_string_lossy());
+        }
+        None => println!("{}", json),
+    }
+    Ok(())
+}
+
+// Repositories to build from a file, either the JSON written by discover or links one per line.
+fn read_repo_list(path: &Path) -> Result<Vec<Repo>, Box<dyn std::error::Error>> {
+    let content = fs::read_to_string(path).map_err(|err| format!("Unable to read {}: {}", path.to_string_lossy(), err))?;
+    if content.trim_start().starts_with('[') {
+        return Ok(serde_json::from_str(&content).map_err(|err| format!("Unable to parse {}: {}", path.to_string_lossy(), err))?);
+    }
+    let mut repos = Vec::new();
+    for link in content.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        match github_api::repo_from_link(link, "list_command_line") {
+            Some(repo) => repos.push(repo),
+            None => log::error!("Unable to name a checkout for {}", link),
+        }
+    }
+    Ok(repos)
+}
+
+// Log the run report and write the manifest for the builds of a run.
+fn finish_run(reports: Vec<Result<RepoReport, JoinError>>, started_at: DateTime<Utc>, results_dir: &Path) {
+    let reports: Vec<RepoReport> = reports
+        .into_iter()
+        .filter_map(|report| report.map_err(|err| log::error!("Build task failed: {}", err)).ok())
+        .collect();
+    log_run_report(&reports);
+    if let Err(err) = write_manifest(&reports, started_at, &results_dir.join("manifest.json")) {
+        log::error!("Error writing the run manifest: {}", err);
+    }
+}
+
+// Clone and build repositories, at most max_builders at a time.
+async fn build_all(repos: Vec<Repo>, options: &Arc<BuildOptions>, sinks: &Sinks, max_builders: usize) -> Vec<Result<RepoReport, JoinError>> {
+    //Set the maximum number of concurrent builders.
+    let semaphore = Arc::new(Semaphore::new(max_builders));
+
+    let builder_tasks = repos
+        .into_iter()
+        .map(|repo| {
+            let semaphore = Arc::clone(&semaphore);
+            let options = Arc::clone(options);
+            let sinks = Arc::clone(sinks);
+            // Spawn a task for each repository
+            spawn(async move {
+                let permit = semaphore.acquire().await.expect("Failed to acquire semaphore permit");
+                // Cloning and building block, keep them off the async workers.
+                let report = spawn_blocking(move || process_results(&repo, &options, &sinks)).await;
+                drop(permit);
+                report
+            })
+        })
+        .collect::<Vec<_>>();
+
+    try_join_all(builder_tasks).await.unwrap()
+}
+
+// Run discovery every interval seconds, build what changed since the last cycle and emit an
+// event for each change to results/events.jsonl and the notifier's hooks.
+async fn watch(state_path: &Path, interval: u64, once: bool, options: &Arc<BuildOptions>, sinks: &Sinks, notifier: &Arc<Notifier>, max_builders: usize) {
+    let events_path = options.results_dir.join("events.jsonl");
+    let mut state = match WatchState::load(state_path) {
+        Ok(state) => state,
+        Err(err) => {
+            log::error!("Unable to load the watch state: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    loop {
+        let cycle_started_at = Utc::now();
+        let discovery = discover(&[]).await;
+        log::info!("Discovered {} repositories", discovery.repos.len());
+        let (changes, mut events) = block_in_place(|| state.changes(discovery.repos, &discovery.failed_parsers));
+        log::info!("{} new or changed repositories, {} removed", changes.len(), events.len());
+
+        let repos = changes.iter().map(|change| change.repo.clone()).collect();
+        let reports: Vec<RepoReport> = build_all(repos, options, sinks, max_builders)
+            .await
+            .into_iter()
+            .filter_map(|report| report.map_err(|err| log::error!("Build task failed: {}", err)).ok())
+            .collect();
+        for change in changes {
+            // A build task that panicked has no report, it's picked up again next cycle.
+            if let Some(report) = reports.iter().find(|report| report.name == change.repo.name && report.parser == change.repo.parser) {
+                events.push(state.record(change, report));
+            }
+        }
+
+        // The cycle gets a manifest of its own. The run manifest keeps every watched repository
+        // so report, diff and serve don't lose the ones this cycle left alone.
+        let removed: Vec<(String, String)> = events
+            .iter()
+            .filter(|event| event.event == EventKind::RemovedRepo)
+            .map(|event| (event.parser.clone(), event.repo.clone()))
+            .collect();
+        if !reports.is_empty() {
+            log_run_report(&reports);
+            let cycle_manifest = options.results_dir.join("watch").join(format!("{}-manifest.json", cycle_started_at.format("%Y%m%dT%H%M%SZ")));
+            if let Err(err) = write_manifest(&reports, cycle_started_at, &cycle_manifest) {
+                log::error!("Error writing the cycle manifest: {}", err);
+            }
+        }
+        if !reports.is_empty() || !removed.is_empty() {
+            if let Err(err) = update_manifest(&reports, &removed, cycle_started_at, &options.results_dir.join("manifest.json")) {
+                log::error!("Error writing the run manifest: {}", err);
+            }
+        }
+        if let Err(err) = emit_events(&events, &events_path) {
+            log::error!("Error writing events: {}", err);
+        }
+        if !events.is_empty() && !notifier.hooks.is_empty() {
+            let notifier = Arc::clone(notifier);
+            if let Err(err) = spawn_blocking(move || notifier.notify(&events)).await {
+                log::error!("Notification task failed: {}", err);
+            }
+        }
+        if let Err(err) = state.save(state_path) {
+            log::error!("Error saving the watch state: {}", err);
+        }
+
+        if once {
+            return;
+        }
+        log::info!("Next discovery in {}s", interval);
+        sleep(Duration::from_secs(interval)).await;
+    }
+}
+
+fn log_run_report(reports: &[RepoReport]) {
+    let failed = reports.iter().filter(|report| report.failure.is_some()).count();
+    let contracts: usize = reports.iter().map(|report| report.contracts).sum();
+    log::info!("Run report: {} repositories, {} built, {} failed, {} contracts", reports.len(), reports.len() - failed, failed, contracts);
+    for report in reports {
+        if let Some(reason) = &report.failure {
+            log::warn!("{} failed: {}", report.name, reason);
+        } else {
+            let command = report.command.as_deref().unwrap_or("no command");
+            let decision = report.decision.as_deref().unwrap_or("no decision");
+            log::info!("{} built {} contracts with '{}', {}", report.name, report.contracts, command, decision);
+        }
+    }
+}
+
+fn process_results(repo: &Repo, options: &BuildOptions, sinks: &[Box<dyn OutputSink>]) -> RepoReport {
+    let started_at = Utc::now();
+    match github_api::clone_repository(repo) {
+        Ok(_) => build_results(repo, options, sinks, started_at),
         Err(err) => {
             log::error!("Error cloning repo: {}", err);
+            RepoReport::failed(repo, RepoOutcome::Error, started_at, format!("Error cloning repo: {}", err))
         }
     }
+}
+
+// Build a repository that is already on disk and write its results. The quarantine policy
+// in options decides what happens to the directory when it doesn't build.
+fn build_results(repo: &Repo, options: &BuildOptions, sinks: &[Box<dyn OutputSink>], started_at: DateTime<Utc>) -> RepoReport {
+    // The checked out commit rather than the reference that was asked for, which may be a
+    // branch. Read before building as a failed checkout may be moved or deleted.
+    let commit = github_api::head_commit(&repo.name).or_else(|| repo.commit.clone());
+    match process_repository(repo, options) {
+        Ok(build) => {
+            if !build.contracts.is_empty() {
+                let builder = build.build.as_ref().map(|attempt| attempt.builder.clone());
+                let command = build.build.and_then(|attempt| attempt.command);
+                let decision = Some(build.decision.reason);
+                let detections = build.decision.detections;
+                let result = ResultEnvelope::new(repo, commit.clone(), builder, command, started_at, build.contracts);
+                let (contracts, sources, missing_sources) = (result.contracts.len(), result.sources.len(), result.header.missing_sources.len());
+                let (builder, command) = (result.header.builder.clone(), result.header.command.clone());
+                match write_results(repo, result, sinks) {
+                    Ok(_) => {
+                        let mut report = RepoReport::new(repo, RepoOutcome::Built, started_at);
+                        report.commit = commit;
+                        report.builder = builder;
+                        report.contracts = contracts;
+                        report.sources = sources;
+                        report.missing_sources = missing_sources;
+                        report.results = sinks.iter().map(|sink| sink.location(repo)).collect();
+                        report.command = command;
+                        report.decision = decision;
+                        report.detections = detections;
+                        report
+                    }
+                    Err(err) => {
+                        log::error!("Error writing results for {}: {}", &repo.name, err);
+                        RepoReport::failed(repo, RepoOutcome::Error, started_at, format!("Error writing results: {}", err))
+                    }
+                }
+            } else {
+                log::error!("No contract output for {}", &repo.name);
+                let outcome = build.failure.as_ref().map(|failure| failure.outcome.into()).unwrap_or(RepoOutcome::Failed);
+                // Only a failure record written for this build is linked, not one left by an earlier run.
+                let failure_record = build.failure.as_ref().and_then(|failure| failure.record_path.clone());
+                let reason = build.failure.map(|failure| failure.reason).unwrap_or_else(|| "No contract output".to_string());
+                let mut report = RepoReport::failed(repo, outcome, started_at, reason);
+                report.commit = commit;
+                report.failure_record = failure_record;
+                report.decision = Some(build.decision.reason);
+                report.detections = build.decision.detections;
+                report
+            }
+        }
+        Err(err) => {
+            log::error!("Error processing repository: {}", err);
+            RepoReport::failed(repo, RepoOutcome::Error, started_at, format!("Error processing repository: {}", err))
+        }
+    }
+}
+
+// Write to every sink, stopping at the first that fails.
+fn write_results(repo: &Repo, mut result: ResultEnvelope, sinks: &[Box<dyn OutputSink>]) -> Result<(), Box<dyn std::error::Error>> {
+    sort_contracts(&mut result.contracts);
+    for sink in sinks {
+        sink.write(repo, &result)?;
+    }
     Ok(())
-}    
\ No newline at end of file
+}
@@ -1,132 +1,281 @@
 use log;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use walkdir::WalkDir;
+use schemars::JsonSchema;
 use serde::Serialize;
 
-use crate::builders::build::Build;
+use crate::builders::artifacts::committed_artifacts;
+use crate::builders::build::{Build, BuildOutput, ProjectConfig};
+use crate::builders::detect::{BuilderKind, Decision, Detection};
 use crate::builders::forge::ForgeBuilder;
-use crate::builders::hardhat::{HardhatBuilder, HardhatMode};
+use crate::builders::hardhat::{is_workspace_root, workspace_root, HardhatBuilder};
 use crate::builders::truffle::TruffleBuilder;
 use crate::parsers::parse::Repo;
+use crate::builders::limits::Limits;
+use crate::builders::runner::Runner;
+use crate::builders::sandbox::Sandbox;
+use crate::quarantine::{quarantine, write_failure_record, FailureRecord, Outcome, QuarantineAction, QuarantinePolicy};
 
-// Contract struct. ContractKind for Contract vs Interfaces. Interfaces have the bytecode 0x
-#[derive(Clone, Debug, Serialize)]
+/// A compiled contract or interface, told apart by kind. Interfaces have the bytecode 0x.
+#[derive(Clone, Debug, Serialize, JsonSchema)]
 pub struct Contract {
     pub contract_name: String,
     pub kind: Kind,
+    /// Creation bytecode, hex with a 0x prefix. 0x for interfaces and abstract contracts.
     pub bytecode: String,
+    /// Runtime bytecode, hex with a 0x prefix.
     pub deployed_bytecode: Option<String>,
+    /// Contracts in the files this contract's source imports.
     pub imports: Option<Vec<Contract>>,
+    /// solc source map of the creation bytecode.
     pub sourcemap: Option<String>,
+    /// solc source map of the runtime bytecode.
     pub deployed_sourcemap: Option<String>,
+    /// Source file path as the compiler saw it, usually relative to the project root.
     pub absolute_path: Option<String>,
+    /// The source unit's AST id, the file index source maps refer to.
     pub id: Option<u32>,
+    /// SHA-256 of the source file, which is kept once in the result's sources with this path
+    /// and hash. Null when the file couldn't be read.
+    pub source_hash: Option<String>,
+    // Text of the source file as the builder read it, moved into the result's sources on output.
+    #[serde(skip)]
     pub file_contents: Option<String>,
 }
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Serialize, JsonSchema)]
 pub enum Kind {
     Interface,
     Contract,
 }
 
-pub fn process_repository(repo: &Repo, keep_unsupported: bool) -> Result<(String, Vec<Contract>), Box<dyn std::error::Error>> {
-    let repo_directory = &repo.name;
-    // If we know how to build the repo but it doesn't work move to error
-    let mut error_directory = String::from("repos/error");
-    let repo_path = std::path::Path::new(repo_directory);
-    if let Ok(repo_name) = repo_path.strip_prefix("repos") {
-        if let Some(name) = repo_name.to_str() {
-            error_directory.push('/');
-            error_directory.push_str(name);
-        } 
+// One builder run against a directory and how many contracts it produced.
+#[derive(Clone, Debug, Serialize)]
+pub struct BuildAttempt {
+    pub builder: String,
+    pub directory: String,
+    pub contracts: usize,
+    // The compile command the builder ended up running, e.g. the project's own build script.
+    pub command: Option<String>,
+    pub config: Option<ProjectConfig>,
+}
+
+impl BuildAttempt {
+    pub fn new(builder: &str, output: &BuildOutput, command: Option<String>) -> Self {
+        Self {
+            builder: builder.to_string(),
+            directory: output.directory.clone(),
+            contracts: output.contracts.len(),
+            command,
+            config: output.config.clone(),
+        }
     }
-    log::debug!("Error directory set to {}", error_directory);
-
-    // We don't know how to build this kind of repo 
-        let unsupported_base = format!("repos/unsupported/{}", &repo.parser);
-        let mut unsupported_directory = String::from(unsupported_base);
-        let repo_path = std::path::Path::new(repo_directory);
-        if let Ok(repo_name) = repo_path.strip_prefix("repos") {
-            if let Some(name) = repo_name.to_str() {
-                unsupported_directory.push('/');
-                unsupported_directory.push_str(name);
-            } 
+}
+
+// Settings for building repositories, mostly from the command line.
+#[derive(Clone, Debug)]
+pub struct BuildOptions {
+    pub quarantine: QuarantinePolicy,
+    pub limits: Limits,
+    pub sandbox: Sandbox,
+    // Foundry profile to build with, passed to every command as FOUNDRY_PROFILE.
+    pub forge_profile: Option<String>,
+    // Only read committed artifacts, never install or compile.
+    pub artifacts_only: bool,
+    // Use this builder instead of detecting one.
+    pub builder: Option<BuilderKind>,
+    // Builders to try after the detected ones, in order.
+    pub fallback: Vec<BuilderKind>,
+    // Where results, logs and failure records are written.
+    pub results_dir: PathBuf,
+}
+
+// The command line's defaults: failed builds moved to repos/error, unsupported repositories
+// deleted, a 30 minute timeout per command and forge as the fallback.
+impl Default for BuildOptions {
+    fn default() -> Self {
+        Self {
+            quarantine: QuarantinePolicy::new(QuarantineAction::Move, QuarantineAction::Delete),
+            limits: Limits { timeout: Some(Duration::from_secs(1800)), ..Default::default() },
+            sandbox: Sandbox::default(),
+            forge_profile: None,
+            artifacts_only: false,
+            builder: None,
+            fallback: vec![BuilderKind::Forge],
+            results_dir: PathBuf::from("results"),
         }
-    log::debug!("Unsupported directory set to {}", unsupported_directory);
+    }
+}
 
-    for entry in WalkDir::new(repo_directory).into_iter().filter_map(|e| e.ok()) {
-        if entry.file_type().is_dir() {
-            let subdir = entry.path();
-            let hardhat_config_ts = subdir.join("hardhat.config.ts");
-            let hardhat_config_js = subdir.join("hardhat.config.js");
-            let foundry_file = subdir.join("foundry.toml");
-            let truffle_file = subdir.join("truffle-config.js");
+// What came out of building a repository. build is the attempt that produced the contracts,
+// failure is set when none were produced.
+pub struct RepositoryBuild {
+    pub contracts: Vec<Contract>,
+    pub build: Option<BuildAttempt>,
+    pub decision: Decision,
+    pub failure: Option<FailureRecord>,
+}
 
-            if hardhat_config_ts.exists() || hardhat_config_js.exists() {
-                let mut builder = HardhatBuilder::new(HardhatMode::Npm);
+impl RepositoryBuild {
+    fn built(contracts: Vec<Contract>, attempts: &[BuildAttempt], decision: Decision) -> Self {
+        Self { contracts, build: attempts.last().cloned(), decision, failure: None }
+    }
+}
 
-                if subdir.join("yarn.lock").exists() {
-                    log::info!("Setting HardhatBuilder to Yarn mode");
-                    builder.set_mode(HardhatMode::Yarn);
-                }
+pub fn process_repository(repo: &Repo, options: &BuildOptions) -> Result<RepositoryBuild, Box<dyn std::error::Error>> {
+    let repo_directory = &repo.name;
+    let mut attempts: Vec<BuildAttempt> = Vec::new();
+    // Builder stdout/stderr for this repository ends up in results/logs/<parser>_<name>
+    let mut runner = Runner::new(options.results_dir.join("logs").join(repo.file_name()), options.limits.clone(), options.sandbox.for_directory(repo_directory));
+    if let Some(profile) = &options.forge_profile {
+        runner.set_env("FOUNDRY_PROFILE", profile);
+    }
 
-                let (directory, contracts) = builder.build(subdir.to_str().unwrap())?;
-                if contracts.is_empty() {
-                    builder.flip_mode(); // Whatever mode you were that didn't work, try the other.
-                    log::info!("No contracts found, trying HardhatBuilder in {:?} mode", builder.mode);
-                    let (directory, contracts) = builder.build(subdir.to_str().unwrap())?;
-                    if contracts.is_empty() {
-                        log::info!("No contracts found, trying FoundryBuilder");
-                        let builder = ForgeBuilder;
-                        let (directory, contracts) = builder.build(subdir.to_str().unwrap())?;
-                        if contracts.is_empty() {
-                            log::error!("Attempted building with Hardhat then Foundry but failed. Moving {} to {}", directory, error_directory);
-                            std::fs::create_dir_all(&error_directory)?;
-                            std::fs::rename(repo_directory, &error_directory)?;
-                            return Ok(("".to_string(), Vec::new())); 
-                        }
-                        return Ok((directory, contracts)) 
-                    } else {
-                        return Ok((directory, contracts))
-                    }
-                } else {
-                    return Ok((directory, contracts))
-                }
-            } else if foundry_file.exists(){
-                let builder = ForgeBuilder;
-                let build_results = builder.build(subdir.to_str().unwrap())?;
-                let (directory, contracts) = &build_results;
-                if contracts.is_empty() {
-                    log::error!("Attempted building with Foundry but failed. Moving repo {} to {}", directory, error_directory);
-                    std::fs::create_dir_all(&error_directory)?;
-                    std::fs::rename(repo_directory, &error_directory)?;
-                    return Ok(("".to_string(), Vec::new()));
+    let mut decision = find_project(Path::new(repo_directory));
+    let project = match (&decision.project, options.builder) {
+        (Some(project), _) => project.clone(),
+        // An explicit builder doesn't need a config file, e.g. forge with the default layout.
+        (None, Some(_)) if Path::new(repo_directory).is_dir() => {
+            decision.project = Some(repo_directory.clone());
+            repo_directory.clone()
+        }
+        (None, _) => {
+            if options.artifacts_only {
+                if let Some((layout, output)) = committed_artifacts(repo_directory, true, &runner) {
+                    attempts.push(BuildAttempt::new(&format!("committed artifacts ({})", layout), &output, None));
+                    return Ok(RepositoryBuild::built(output.contracts, &attempts, decision))
                 }
-                return Ok(build_results)
-            } else if truffle_file.exists(){
-                let builder = TruffleBuilder;
-                let (directory, contracts) = builder.build(subdir.to_str().unwrap())?;
-                if contracts.is_empty() {
-                    log::error!("Attempted building with Truffle but failed. Moving {} to {}", directory, error_directory);
-                    std::fs::create_dir_all(&error_directory)?;
-                    std::fs::rename(repo_directory, &error_directory)?;
-                    return Ok(("".to_string(), Vec::new()));
+            }
+            let reason = decision.reason.clone();
+            let mut record = FailureRecord::new(repo, Outcome::Unsupported, &reason, decision.clone(), attempts, &runner);
+            if decision.detections.is_empty() {
+                // Nothing on disk to quarantine, but the record is still written.
+                log::error!("{}", reason);
+                match write_failure_record(repo, &record, &options.results_dir) {
+                    Ok(record_path) => record.record_path = Some(record_path),
+                    Err(err) => log::error!("Error writing the failure record for {}: {}", &repo.name, err),
                 }
-                return Ok((directory, contracts))
+                return Ok(RepositoryBuild { contracts: Vec::new(), build: None, decision, failure: Some(record) });
+            }
+            return failed(repo, record, decision, options);
+        }
+    };
+
+    // Committed artifacts that match the sources save installing and compiling.
+    if let Some((layout, output)) = committed_artifacts(&project, options.artifacts_only, &runner) {
+        attempts.push(BuildAttempt::new(&format!("committed artifacts ({})", layout), &output, None));
+        decision.reason = format!("committed {} artifacts match the sources", layout);
+        return Ok(RepositoryBuild::built(output.contracts, &attempts, decision))
+    }
+    if options.artifacts_only {
+        let record = FailureRecord::new(repo, Outcome::Failed, "No committed artifacts found and only artifacts were asked for", decision.clone(), attempts, &runner);
+        return failed(repo, record, decision, options);
+    }
+
+    decision.plan(options.builder, &options.fallback);
+    log::info!("Building {} with {}: {}", project, join(&decision.chain), decision.reason);
+
+    for builder in &decision.chain {
+        let contracts = run_builder(*builder, Path::new(&project), Path::new(repo_directory), &runner, &mut attempts)?;
+        if !contracts.is_empty() {
+            return Ok(RepositoryBuild::built(contracts, &attempts, decision))
+        }
+        log::info!("No contracts found with {}", builder);
+    }
+
+    let tried = attempts.iter().map(|attempt| attempt.builder.clone()).collect::<Vec<_>>().join(", ");
+    let reason = format!("Attempted building with {} but failed", tried);
+    let record = FailureRecord::new(repo, Outcome::Failed, &reason, decision.clone(), attempts, &runner);
+    failed(repo, record, decision, options)
+}
+
+// Walk down from the repository root to the first directory a builder is configured in. Only
+// monorepo roots are looked through, any other directory without a config means the
+// repository isn't supported.
+fn find_project(repo_directory: &Path) -> Decision {
+    let mut decision = Decision::default();
+    // Set once we pass a monorepo root, its packages are further down.
+    let mut workspace: Option<PathBuf> = None;
+
+    let walker = WalkDir::new(repo_directory).into_iter().filter_entry(|e| !is_dependency_directory(e.path()));
+    for entry in walker.filter_map(|e| e.ok()) {
+        if entry.file_type().is_dir() {
+            let subdir = entry.path();
+            let detection = Detection::scan(subdir);
+            let is_project = detection.is_project();
+            decision.detections.push(detection);
+
+            if is_project {
+                decision.project = Some(subdir.to_string_lossy().to_string());
+                return decision;
+            } else if is_workspace_root(subdir) || workspace.as_ref().is_some_and(|root| subdir.starts_with(root)) {
+                // Keep looking for the package with the contracts in it.
+                workspace.get_or_insert_with(|| subdir.to_path_buf());
             } else {
-                if keep_unsupported {
-                    log::error!("No buildable file found. Moving repo to {}", unsupported_directory);
-                    std::fs::create_dir_all(&unsupported_directory)?;
-                    std::fs::rename(repo_directory, &unsupported_directory)?;
-                    return Ok(("".to_string(), Vec::new()));
-                } else {
-                    log::error!("No buildable file found. Deleting repo: {}", repo_directory);
-                    std::fs::remove_dir_all(&repo_directory)?;
+                decision.reason = "No buildable file found".to_string();
+                return decision;
+            }
+        }
+    }
+    decision.reason = if workspace.is_some() {
+        "No buildable package found in the workspace".to_string()
+    } else {
+        "No directories found in the repository".to_string()
+    };
+    decision
+}
+
+// Run one builder against the project, recording an attempt for each run. Hardhat is tried
+// with each package manager in turn, the lockfile's first.
+fn run_builder(builder: BuilderKind, project: &Path, repo_directory: &Path, runner: &Runner, attempts: &mut Vec<BuildAttempt>) -> Result<Vec<Contract>, Box<dyn std::error::Error>> {
+    let directory = project.to_str().unwrap();
+    match builder {
+        BuilderKind::Hardhat => {
+            let install_root = workspace_root(project, repo_directory);
+            if install_root != project {
+                log::info!("{} is a workspace package, installing from {}", directory, install_root.to_string_lossy());
+            }
+            for mode in HardhatBuilder::modes(project, &install_root) {
+                log::info!("Trying HardhatBuilder in {:?} mode", mode);
+                let mut builder = HardhatBuilder::new(mode);
+                builder.set_install_root(&install_root);
+                let output = builder.build(directory, runner)?;
+                attempts.push(BuildAttempt::new(&format!("hardhat ({:?})", mode), &output, runner.last_command()));
+                if !output.contracts.is_empty() {
+                    return Ok(output.contracts);
                 }
             }
+            Ok(Vec::new())
+        }
+        BuilderKind::Forge => {
+            let output = ForgeBuilder.build(directory, runner)?;
+            attempts.push(BuildAttempt::new("forge", &output, runner.last_command()));
+            Ok(output.contracts)
+        }
+        BuilderKind::Truffle => {
+            let output = TruffleBuilder.build(directory, runner)?;
+            attempts.push(BuildAttempt::new("truffle", &output, runner.last_command()));
+            Ok(output.contracts)
         }
     }
-    // If none of the builders have returned we don't have anything.
-    log::error!("No contracts returned from builders and we didn't exit earlier.");
-    Ok(("".to_string(), Vec::new()))
-}
\ No newline at end of file
+}
+
+fn join(builders: &[BuilderKind]) -> String {
+    builders.iter().map(|builder| builder.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+// Installed dependencies and git metadata never hold the project's own build config.
+fn is_dependency_directory(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name == "node_modules" || name == ".git")
+}
+
+fn failed(repo: &Repo, record: FailureRecord, decision: Decision, options: &BuildOptions) -> Result<RepositoryBuild, Box<dyn std::error::Error>> {
+    let record = quarantine(repo, record, &options.quarantine, &options.results_dir)?;
+    Ok(RepositoryBuild {
+        contracts: Vec::new(),
+        build: None,
+        decision,
+        failure: Some(record),
+    })
+}
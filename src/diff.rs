@@ -0,0 +1,277 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use clap::ValueEnum;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::output::schema::sha256_hex;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum DiffFormat {
+    Text,
+    Json,
+}
+
+// The parts of a run manifest and result files the diff needs. Read loosely so runs written
+// by older versions still compare.
+#[derive(Deserialize)]
+struct StoredManifest {
+    // Manifests before MANIFEST_VERSION called it schema_version.
+    #[serde(default, alias = "schema_version")]
+    result_schema_version: u32,
+    repos: Vec<StoredRepo>,
+}
+
+#[derive(Deserialize)]
+struct StoredRepo {
+    parser: String,
+    repo: String,
+    url: String,
+    commit: Option<String>,
+    outcome: String,
+    #[serde(default)]
+    results: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct StoredResult {
+    contracts: Vec<StoredContract>,
+}
+
+#[derive(Deserialize)]
+struct StoredContract {
+    contract_name: String,
+    absolute_path: Option<String>,
+    bytecode: String,
+    deployed_bytecode: Option<String>,
+    source_hash: Option<String>,
+    // Results before schema version 2 carry the source text instead of a hash.
+    file_contents: Option<String>,
+}
+
+// A contract as compared between runs.
+struct Fingerprint {
+    source_hash: Option<String>,
+    bytecode_hash: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RepoRef {
+    pub parser: String,
+    pub repo: String,
+    pub url: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ContractChange {
+    // path:Name, the fully qualified name solc uses.
+    pub contract: String,
+    pub source_changed: bool,
+    pub bytecode_changed: bool,
+}
+
+// What changed in a repository that is in both runs.
+#[derive(Clone, Debug, Serialize)]
+pub struct RepoDiff {
+    pub parser: String,
+    pub repo: String,
+    pub commit_from: Option<String>,
+    pub commit_to: Option<String>,
+    pub outcome_from: String,
+    pub outcome_to: String,
+    pub new_contracts: Vec<String>,
+    pub removed_contracts: Vec<String>,
+    pub changed_contracts: Vec<ContractChange>,
+    // Why the contracts couldn't be compared, e.g. a run without JSON results.
+    pub error: Option<String>,
+}
+
+impl RepoDiff {
+    fn is_empty(&self) -> bool {
+        self.error.is_none()
+            && self.commit_from == self.commit_to
+            && self.outcome_from == self.outcome_to
+            && self.new_contracts.is_empty()
+            && self.removed_contracts.is_empty()
+            && self.changed_contracts.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RunDiff {
+    pub from: String,
+    pub to: String,
+    pub new_repos: Vec<RepoRef>,
+    pub removed_repos: Vec<RepoRef>,
+    // Only repositories with a difference.
+    pub changed_repos: Vec<RepoDiff>,
+}
+
+// Compare two runs, each a results directory or the path of its manifest.json.
+pub fn diff_runs(from: &Path, to: &Path) -> Result<RunDiff, Box<dyn std::error::Error>> {
+    let (from_dir, from_manifest) = load_manifest::<StoredManifest>(from)?;
+    let (to_dir, to_manifest) = load_manifest::<StoredManifest>(to)?;
+    if from_manifest.result_schema_version != to_manifest.result_schema_version {
+        log::warn!("Comparing runs written with result schema versions {} and {}", from_manifest.result_schema_version, to_manifest.result_schema_version);
+    }
+    let from_repos = by_key(from_manifest.repos);
+    let mut to_repos = by_key(to_manifest.repos);
+
+    let mut diff = RunDiff {
+        from: from.to_string_lossy().to_string(),
+        to: to.to_string_lossy().to_string(),
+        new_repos: Vec::new(),
+        removed_repos: Vec::new(),
+        changed_repos: Vec::new(),
+    };
+    for (key, old) in from_repos {
+        let new = match to_repos.remove(&key) {
+            Some(new) => new,
+            None => {
+                diff.removed_repos.push(repo_ref(&old));
+                continue;
+            }
+        };
+        let mut repo_diff = RepoDiff {
+            parser: new.parser.clone(),
+            repo: new.repo.clone(),
+            commit_from: old.commit.clone(),
+            commit_to: new.commit.clone(),
+            outcome_from: old.outcome.clone(),
+            outcome_to: new.outcome.clone(),
+            new_contracts: Vec::new(),
+            removed_contracts: Vec::new(),
+            changed_contracts: Vec::new(),
+            error: None,
+        };
+        // Contracts are only compared when both runs built the repository, a failed build
+        // shows up as the outcome changing.
+        if old.outcome == "built" && new.outcome == "built" {
+            // Results that were pruned or written in another format leave just this repository
+            // uncompared.
+            let (mut old_contracts, new_contracts) = match (load_contracts(&from_dir, &old), load_contracts(&to_dir, &new)) {
+                (Ok(old_contracts), Ok(new_contracts)) => (old_contracts, new_contracts),
+                (Err(err), _) | (_, Err(err)) => {
+                    log::warn!("Not comparing the contracts of {} {}: {}", new.parser, new.repo, err);
+                    repo_diff.error = Some(format!("results missing: {}", err));
+                    diff.changed_repos.push(repo_diff);
+                    continue;
+                }
+            };
+            for (contract, fingerprint) in new_contracts {
+                match old_contracts.remove(&contract) {
+                    None => repo_diff.new_contracts.push(contract),
+                    Some(previous) => {
+                        let source_changed = previous.source_hash != fingerprint.source_hash;
+                        let bytecode_changed = previous.bytecode_hash != fingerprint.bytecode_hash;
+                        if source_changed || bytecode_changed {
+                            repo_diff.changed_contracts.push(ContractChange { contract, source_changed, bytecode_changed });
+                        }
+                    }
+                }
+            }
+            repo_diff.removed_contracts = old_contracts.into_keys().collect();
+        }
+        if !repo_diff.is_empty() {
+            diff.changed_repos.push(repo_diff);
+        }
+    }
+    diff.new_repos = to_repos.values().map(repo_ref).collect();
+    Ok(diff)
+}
+
+// The run's results directory and its manifest, given either.
+pub(crate) fn load_manifest<T: DeserializeOwned>(run: &Path) -> Result<(PathBuf, T), Box<dyn std::error::Error>> {
+    let (directory, manifest) = if run.is_dir() {
+        (run.to_path_buf(), run.join("manifest.json"))
+    } else {
+        (run.parent().unwrap_or(Path::new(".")).to_path_buf(), run.to_path_buf())
+    };
+    let content = fs::read_to_string(&manifest).map_err(|err| format!("Unable to read {}: {}", manifest.to_string_lossy(), err))?;
+    let manifest = serde_json::from_str(&content).map_err(|err| format!("Unable to parse {}: {}", manifest.to_string_lossy(), err))?;
+    Ok((directory, manifest))
+}
+
+// Repositories by parser and name, the same repository found by two parsers is two entries.
+fn by_key(repos: Vec<StoredRepo>) -> BTreeMap<(String, String), StoredRepo> {
+    repos.into_iter().map(|repo| ((repo.parser.clone(), repo.repo.clone()), repo)).collect()
+}
+
+fn repo_ref(repo: &StoredRepo) -> RepoRef {
+    RepoRef { parser: repo.parser.clone(), repo: repo.repo.clone(), url: repo.url.clone() }
+}
+
+// The repository's contracts from its JSON results, by fully qualified name.
+fn load_contracts(directory: &Path, repo: &StoredRepo) -> Result<BTreeMap<String, Fingerprint>, Box<dyn std::error::Error>> {
+    let path = match repo.results.iter().find(|path| path.ends_with("_contracts.json")) {
+        Some(path) => directory.join(path),
+        None => return Err(format!("{} {} has no JSON results, runs are compared with --output-format json", repo.parser, repo.repo).into()),
+    };
+    let content = fs::read_to_string(&path).map_err(|err| format!("Unable to read {}: {}", path.to_string_lossy(), err))?;
+    let result: StoredResult = serde_json::from_str(&content).map_err(|err| format!("Unable to parse {}: {}", path.to_string_lossy(), err))?;
+
+    Ok(result
+        .contracts
+        .into_iter()
+        .map(|contract| {
+            let name = format!("{}:{}", contract.absolute_path.as_deref().unwrap_or_default(), contract.contract_name);
+            let source_hash = contract.source_hash.or_else(|| contract.file_contents.map(|content| sha256_hex(content.as_bytes())));
+            let bytecode = contract.deployed_bytecode.unwrap_or(contract.bytecode);
+            (name, Fingerprint { source_hash, bytecode_hash: sha256_hex(bytecode.as_bytes()) })
+        })
+        .collect())
+}
+
+pub fn print_diff(diff: &RunDiff, format: DiffFormat) -> Result<(), Box<dyn std::error::Error>> {
+    if format == DiffFormat::Json {
+        println!("{}", serde_json::to_string_pretty(diff)?);
+        return Ok(());
+    }
+
+    println!("{} -> {}", diff.from, diff.to);
+    if diff.new_repos.is_empty() && diff.removed_repos.is_empty() && diff.changed_repos.is_empty() {
+        println!("No differences");
+        return Ok(());
+    }
+    for repo in &diff.new_repos {
+        println!("+ {} {} ({})", repo.parser, repo.repo, repo.url);
+    }
+    for repo in &diff.removed_repos {
+        println!("- {} {} ({})", repo.parser, repo.repo, repo.url);
+    }
+    for repo in &diff.changed_repos {
+        println!("~ {} {}", repo.parser, repo.repo);
+        if repo.commit_from != repo.commit_to {
+            println!("    commit {} -> {}", short(&repo.commit_from), short(&repo.commit_to));
+        }
+        if repo.outcome_from != repo.outcome_to {
+            println!("    outcome {} -> {}", repo.outcome_from, repo.outcome_to);
+        }
+        if let Some(error) = &repo.error {
+            println!("    ! {}", error);
+        }
+        for contract in &repo.new_contracts {
+            println!("    + {}", contract);
+        }
+        for contract in &repo.removed_contracts {
+            println!("    - {}", contract);
+        }
+        for change in &repo.changed_contracts {
+            let what: Vec<&str> = [(change.source_changed, "source"), (change.bytecode_changed, "bytecode")]
+                .iter()
+                .filter(|(changed, _)| *changed)
+                .map(|(_, what)| *what)
+                .collect();
+            println!("    ~ {} ({})", change.contract, what.join(", "));
+        }
+    }
+    Ok(())
+}
+
+fn short(commit: &Option<String>) -> &str {
+    match commit {
+        Some(commit) => &commit[..commit.len().min(12)],
+        None => "none",
+    }
+}
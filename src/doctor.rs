@@ -0,0 +1,179 @@
+use std::env;
+use std::ffi::CString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use rustarena::builders::detect::BuilderKind;
+use rustarena::builders::runner::on_path;
+
+// Package managers and tools only some projects or options need.
+const OPTIONAL_TOOLS: [(&str, &str); 5] = [
+    ("yarn", "hardhat projects with a yarn.lock"),
+    ("pnpm", "hardhat projects with a pnpm-lock.yaml"),
+    ("bun", "hardhat projects with a bun.lockb"),
+    ("bunx", "hardhat projects with a bun.lockb"),
+    ("bwrap", "--sandbox bwrap"),
+];
+
+// Below this builds start failing in confusing ways, node_modules alone run to gigabytes.
+const LOW_DISK_BYTES: u64 = 10 * 1024 * 1024 * 1024;
+const CRITICAL_DISK_BYTES: u64 = 1024 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+struct Check {
+    name: String,
+    status: Status,
+    detail: String,
+}
+
+impl Check {
+    fn new(name: &str, status: Status, detail: String) -> Self {
+        Self { name: name.to_string(), status, detail }
+    }
+}
+
+// Check everything builds depend on and print a readiness table. Returns false when nothing
+// could be built, either because no builder has its toolchain or the disk is full.
+pub fn run() -> bool {
+    let mut checks = Vec::new();
+    let mut ready_builders = 0;
+
+    let builders = [BuilderKind::Hardhat, BuilderKind::Forge, BuilderKind::Truffle];
+    let mut tools: Vec<&str> = Vec::new();
+    for tool in builders.iter().flat_map(|builder| builder.tools()) {
+        if !tools.contains(tool) {
+            tools.push(tool);
+            checks.push(check_tool(tool, Status::Fail, &needed_by(tool, &builders)));
+        }
+    }
+    for (tool, needed_by) in OPTIONAL_TOOLS {
+        checks.push(check_tool(tool, Status::Warn, needed_by));
+    }
+
+    for builder in builders {
+        let missing: Vec<&str> = builder.tools().iter().filter(|tool| !on_path(tool)).copied().collect();
+        let name = format!("builder {}", builder);
+        if missing.is_empty() {
+            ready_builders += 1;
+            checks.push(Check::new(&name, Status::Ok, "ready".to_string()));
+        } else {
+            checks.push(Check::new(&name, Status::Fail, format!("needs {}", missing.join(", "))));
+        }
+    }
+
+    checks.push(check_chrome());
+    checks.push(check_solc_cache());
+    let disk = check_disk(Path::new("."));
+    let disk_full = disk.status == Status::Fail;
+    checks.push(disk);
+
+    print_table(&checks);
+    ready_builders > 0 && !disk_full
+}
+
+fn needed_by(tool: &str, builders: &[BuilderKind]) -> String {
+    let builders: Vec<String> = builders.iter().filter(|builder| builder.tools().contains(&tool)).map(|builder| builder.to_string()).collect();
+    format!("{} builder", builders.join(", "))
+}
+
+// status is what a missing tool counts as.
+fn check_tool(tool: &str, status: Status, needed_by: &str) -> Check {
+    if !on_path(tool) {
+        return Check::new(tool, status, format!("not found, needed by {}", needed_by));
+    }
+    match version(Path::new(tool)) {
+        Some(version) => Check::new(tool, Status::Ok, version),
+        None => Check::new(tool, Status::Warn, "found but --version failed".to_string()),
+    }
+}
+
+// First line of `program --version`.
+fn version(program: &Path) -> Option<String> {
+    let output = Command::new(program).arg("--version").stdin(Stdio::null()).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout.lines().map(str::trim).find(|line| !line.is_empty()).map(str::to_string)
+}
+
+// The binary headless_chrome will launch for the code4rena and immunefi parsers.
+fn check_chrome() -> Check {
+    match headless_chrome::browser::default_executable() {
+        Ok(path) => {
+            let detail = match version(&path) {
+                Some(version) => format!("{} ({})", version, path.to_string_lossy()),
+                None => path.to_string_lossy().to_string(),
+            };
+            Check::new("chrome", Status::Ok, detail)
+        }
+        Err(_) => Check::new("chrome", Status::Warn, "not found, needed by the code4rena and immunefi parsers. Set CHROME to its path".to_string()),
+    }
+}
+
+// Compilers svm (forge) and hardhat have already downloaded. Without them builds need network
+// access to fetch solc, which --sandbox-network install doesn't give the compile step.
+fn check_solc_cache() -> Check {
+    let home = match env::var_os("HOME") {
+        Some(home) => PathBuf::from(home),
+        None => return Check::new("solc cache", Status::Warn, "HOME is not set".to_string()),
+    };
+    let caches = [home.join(".svm"), home.join(".cache/hardhat-nodejs/compilers-v2"), home.join(".cache/hardhat-nodejs/compilers")];
+    let mut versions: Vec<String> = Vec::new();
+    for cache in caches.iter().filter(|cache| cache.is_dir()) {
+        for entry in fs::read_dir(cache).into_iter().flatten().flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if entry.path().is_dir() && name.chars().next().is_some_and(|c| c.is_ascii_digit()) && !versions.contains(&name) {
+                versions.push(name);
+            }
+        }
+    }
+    if versions.is_empty() {
+        Check::new("solc cache", Status::Warn, "no compilers cached, solc will be downloaded during builds".to_string())
+    } else {
+        versions.sort();
+        Check::new("solc cache", Status::Ok, format!("{} versions: {}", versions.len(), versions.join(", ")))
+    }
+}
+
+// Free space where repos/ and results/ are written.
+fn check_disk(directory: &Path) -> Check {
+    let path = match CString::new(directory.to_string_lossy().as_bytes()) {
+        Ok(path) => path,
+        Err(err) => return Check::new("disk", Status::Warn, err.to_string()),
+    };
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Check::new("disk", Status::Warn, std::io::Error::last_os_error().to_string());
+    }
+    let free = stat.f_bavail as u64 * stat.f_frsize as u64;
+    let detail = format!("{:.1} GiB free", free as f64 / (1024.0 * 1024.0 * 1024.0));
+    let status = if free < CRITICAL_DISK_BYTES {
+        Status::Fail
+    } else if free < LOW_DISK_BYTES {
+        Status::Warn
+    } else {
+        Status::Ok
+    };
+    Check::new("disk", status, detail)
+}
+
+fn print_table(checks: &[Check]) {
+    let width = checks.iter().map(|check| check.name.len()).max().unwrap_or(0);
+    println!("{:<width$}  {:<7}  DETAIL", "CHECK", "STATUS", width = width);
+    for check in checks {
+        let status = match check.status {
+            Status::Ok => "ok",
+            Status::Warn => "warn",
+            Status::Fail => "fail",
+        };
+        println!("{:<width$}  {:<7}  {}", check.name, status, check.detail, width = width);
+    }
+}
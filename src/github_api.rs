@@ -1,57 +1,201 @@
-use log;
 use std::fs;
-use git2::{Oid, Repository};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use flate2::read::GzDecoder;
+use git2::{Direction, Object, Remote, Repository};
 use url::Url;
 
 use crate::parsers::parse::Repo;
+use crate::source_host;
 
+/// Clone the repository into repo.name, or unpack it when the url is an archive. Downloads use
+/// reqwest::blocking, which panics inside an async runtime, so async callers must run this in
+/// tokio::task::spawn_blocking.
 pub fn clone_repository(repository: &Repo) -> Result<(), Box<dyn std::error::Error>> {
     // Check if the directory exists
     if fs::metadata(&repository.name).is_ok() {
         // Remove the directory if it exists
         fs::remove_dir_all(&repository.name)?;
     }
-    
-    // Clone the repository
+
+    // Code delivered as a zip or tarball is unpacked rather than cloned.
+    let local_archive = Path::new(&repository.url);
+    if is_archive(&repository.url) && local_archive.is_file() {
+        log::info!("Unpacking the archive {} into {}", &repository.url, &repository.name);
+        return unpack_archive(local_archive, Path::new(&repository.name));
+    }
+
+    if let Err(err) = git_clone(repository) {
+        log::error!("Error cloning {}: {}. Falling back to a source archive", &repository.url, err);
+        if fs::metadata(&repository.name).is_ok() {
+            fs::remove_dir_all(&repository.name)?;
+        }
+        return download_archive(repository)
+            .map_err(|archive_err| format!("clone failed ({}) and archive fallback failed ({})", err, archive_err).into());
+    }
+    Ok(())
+}
+
+fn git_clone(repository: &Repo) -> Result<(), Box<dyn std::error::Error>> {
+    // Clone the repository. git2 speaks plain git so this works for any source host.
     if repository.commit.is_none() {
         log::info!("Cloning the repo {}", &repository.url);
-        Repository::clone(&repository.url, &repository.name).map_err(|err| {
-            err
-        })?;
-    } else {
-        if let Some(commit) = &repository.commit {
-            log::info!("Cloning the repo {} at commit {}", &repository.url, &commit);
-            Repository::clone(&repository.url, &repository.name).map_err(|err| {
-                err
-            })?;
-            let repo: Repository = Repository::open(&repository.name).unwrap();
-            let obj = repo.find_commit(Oid::from_str(commit).unwrap()).unwrap().into_object();
-            repo.checkout_tree(&obj, None).unwrap();
-            repo.set_head_detached(obj.id()).unwrap();
-        }
+        Repository::clone(&repository.url, &repository.name)?;
+    } else if let Some(commit) = &repository.commit {
+        log::info!("Cloning the repo {} at commit {}", &repository.url, &commit);
+        let repo = Repository::clone(&repository.url, &repository.name)?;
+        let obj = resolve_reference(&repo, commit)?;
+        repo.checkout_tree(&obj, None)?;
+        repo.set_head_detached(obj.peel_to_commit()?.id())?;
     }
     Ok(())
 }
 
-pub fn parse_github_url(url: &str) -> Option<(String, String, String)> {
-    let parsed_url = Url::parse(url).ok()?;
+// The commit checked out in directory, None for archives and anything else without git metadata.
+pub fn head_commit(directory: &str) -> Option<String> {
+    let repo = Repository::open(directory).ok()?;
+    let commit = repo.head().ok()?.peel_to_commit().ok()?;
+    Some(commit.id().to_string())
+}
+
+// The upstream commit a clone of repository would check out, like `git ls-remote`. A pinned
+// commit hash never moves and is returned as is, other references are looked up as branches and tags.
+pub fn remote_commit(repository: &Repo) -> Result<String, Box<dyn std::error::Error>> {
+    if let Some(commit) = &repository.commit {
+        if commit.len() >= 7 && commit.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Ok(commit.to_lowercase());
+        }
+    }
+    let mut remote = Remote::create_detached(repository.url.as_str())?;
+    let connection = remote.connect_auth(Direction::Fetch, None, None)?;
+    let heads = connection.list()?;
+    let wanted: Vec<String> = match &repository.commit {
+        Some(reference) => reference_prefixes(reference)
+            .into_iter()
+            .flat_map(|reference| [format!("refs/heads/{}", reference), format!("refs/tags/{}^{{}}", reference), format!("refs/tags/{}", reference)])
+            .collect(),
+        None => vec!["HEAD".to_string()],
+    };
+    wanted
+        .iter()
+        .find_map(|name| heads.iter().find(|head| head.name() == name))
+        .map(|head| head.oid().to_string())
+        .ok_or_else(|| format!("{} has no {}", repository.url, wanted[0]).into())
+}
+
+// Links from non-GitHub hosts often point at a branch or tag rather than a commit so
+// try the reference as given and then as a remote branch.
+fn resolve_reference<'a>(repo: &'a Repository, reference: &str) -> Result<Object<'a>, git2::Error> {
+    let mut first_error = None;
+    for reference in reference_prefixes(reference) {
+        match repo.revparse_single(reference).or_else(|_| repo.revparse_single(&format!("origin/{}", reference))) {
+            Ok(object) => return Ok(object),
+            Err(err) => {
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+    Err(first_error.unwrap_or_else(|| git2::Error::from_str("empty reference")))
+}
+
+// A reference from a link may run on into a path, e.g. feature/x/contracts from a tree
+// link. Longest first: feature/x/contracts, feature/x, feature.
+fn reference_prefixes(reference: &str) -> Vec<&str> {
+    let mut prefixes = vec![reference];
+    while let Some((prefix, _)) = prefixes[prefixes.len() - 1].rsplit_once('/') {
+        prefixes.push(prefix);
+    }
+    prefixes
+}
+
+// Fetch the host's tarball of the pinned commit (or default branch) and unpack it
+// where the clone would have been.
+fn download_archive(repository: &Repo) -> Result<(), Box<dyn std::error::Error>> {
+    let archive_url = source_host::parse_link(&repository.url)
+        .and_then(|link| link.archive_url(repository.commit.as_deref()))
+        .ok_or_else(|| format!("no archive download available for {}", &repository.url))?;
+
+    log::info!("Downloading the archive {}", archive_url);
+    let response = reqwest::blocking::get(&archive_url)?;
+    if !response.status().is_success() {
+        return Err(format!("archive download {} returned {}", archive_url, response.status()).into());
+    }
+    let bytes = response.bytes()?;
+    unpack_into(Path::new(&repository.name), |staging| {
+        tar::Archive::new(GzDecoder::new(Cursor::new(bytes))).unpack(staging)?;
+        Ok(())
+    })
+}
 
-    // Extract the repository URL
-    let repository_url = parsed_url.join("..").map_err(|_| ()).ok()?.into();
+pub fn is_archive(path: &str) -> bool {
+    let path = path.to_lowercase();
+    [".zip", ".tar.gz", ".tgz", ".tar"].iter().any(|extension| path.ends_with(extension))
+}
 
-    // Extract the repository path
-    let path_segments = parsed_url.path_segments()?;
-    let repository = path_segments.clone().take(2).collect::<Vec<_>>().join("/");
+// File name of an archive without its (possibly double) extension.
+pub fn archive_stem(path: &str) -> Option<String> {
+    let file_name = Path::new(path).file_name()?.to_str()?;
+    let lower = file_name.to_lowercase();
+    [".tar.gz", ".tgz", ".tar", ".zip"]
+        .iter()
+        .find(|extension| lower.ends_with(*extension))
+        .map(|extension| file_name[..file_name.len() - extension.len()].to_string())
+}
 
-    // Extract the SHA hash from the branch or commit path segment
-    let sha_hash = path_segments.last()?.to_string();
+pub fn unpack_archive(archive: &Path, destination: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let archive_name = archive.to_string_lossy().to_lowercase();
+    unpack_into(destination, |staging| {
+        let file = fs::File::open(archive)?;
+        if archive_name.ends_with(".zip") {
+            zip::ZipArchive::new(file)?.extract(staging)?;
+        } else if archive_name.ends_with(".tar") {
+            tar::Archive::new(file).unpack(staging)?;
+        } else {
+            tar::Archive::new(GzDecoder::new(file)).unpack(staging)?;
+        }
+        Ok(())
+    })
+}
+
+// Unpack into a staging directory next to the destination. Host archives wrap everything
+// in a single <repo>-<ref> directory which is hoisted so the destination looks like a clone.
+fn unpack_into<F>(destination: &Path, unpack: F) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: FnOnce(&Path) -> Result<(), Box<dyn std::error::Error>>,
+{
+    let staging = PathBuf::from(format!("{}.unpack", destination.display()));
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    fs::create_dir_all(&staging)?;
+    if let Err(err) = unpack(&staging) {
+        fs::remove_dir_all(&staging)?;
+        return Err(err);
+    }
+
+    let entries: Vec<_> = fs::read_dir(&staging)?.filter_map(|e| e.ok()).collect();
+    if entries.len() == 1 && entries[0].path().is_dir() {
+        fs::rename(entries[0].path(), destination)?;
+        fs::remove_dir_all(&staging)?;
+    } else {
+        fs::rename(&staging, destination)?;
+    }
+    Ok(())
+}
 
-    Some((repository_url, repository, sha_hash))
+// The repository a link points to. Links on known hosts are normalised, anything else is
+// cloned as given. None if the link has no path to name the checkout after.
+pub fn repo_from_link(link: &str, parser: &str) -> Option<Repo> {
+    let (url, name, commit) = match source_host::parse_link(link) {
+        Some(source_link) => (source_link.url, source_link.name, source_link.reference),
+        None => (link.to_string(), get_last_path_part(link)?, None),
+    };
+    Some(Repo { parser: parser.to_string(), name: format!("repos/{}", name), url, commit })
 }
 
 pub fn get_last_path_part(url: &str) -> Option<String> {
     if let Ok(parsed_url) = Url::parse(url) {
-        parsed_url.path_segments()?.last().map(String::from)
+        parsed_url.path_segments()?.next_back().map(String::from)
     } else {
         None
     }
@@ -0,0 +1,59 @@
+//! Finds smart contract repositories on bounty platforms, builds them and collects the compiled
+//! contracts. The rustarena binary is a CLI over this library.
+//!
+//! The parts meant for reuse:
+//! - parsers: list the repositories a platform has in scope, as Repo
+//! - github_api::clone_repository: clone a Repo, or unpack its archive, into repos/. It blocks, so
+//!   call it from tokio::task::spawn_blocking in async code
+//! - contract::process_repository: pick a builder for a checkout and build it into Contracts
+//! - builders: the Build trait and the hardhat, forge and truffle builders behind it
+//! - output: result envelopes and the sinks they are written with
+pub mod github_api;
+pub mod source_host;
+pub mod contract;
+pub mod quarantine;
+pub mod diff;
+pub mod report;
+pub mod watch;
+pub mod notify;
+pub mod serve;
+// The original parsers and artifact processors predate clippy running with -D warnings and
+// are left as they are rather than reformatted alongside unrelated changes.
+pub mod parsers {
+    pub mod parse;
+    #[allow(dead_code)]
+    pub mod sherlock;
+    #[allow(dead_code)]
+    pub mod code4rena;
+    #[allow(dead_code)]
+    pub mod immunefi;
+    #[allow(dead_code)]
+    pub mod hats;
+}
+pub mod builders {
+    pub mod artifacts;
+    pub mod build;
+    pub mod detect;
+    pub mod limits;
+    pub mod runner;
+    pub mod sandbox;
+    pub mod forge;
+    #[allow(clippy::needless_borrows_for_generic_args)]
+    pub mod hardhat;
+    #[allow(clippy::needless_borrows_for_generic_args)]
+    pub mod truffle;
+}
+pub mod output {
+    pub mod sink;
+    pub mod schema;
+    pub mod manifest;
+    pub mod json;
+    pub mod jsonl;
+    pub mod sqlite;
+    pub mod files;
+}
+
+pub use builders::build::{Build, BuildOutput};
+pub use contract::{process_repository, BuildOptions, Contract, Kind, RepositoryBuild};
+pub use github_api::clone_repository;
+pub use parsers::parse::Repo;
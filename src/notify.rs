@@ -0,0 +1,239 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::Deserialize;
+
+use crate::watch::{EventKind, WatchEvent};
+
+// How long a webhook or command gets before it is given up on, so a stuck hook can't stall watch.
+const HOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+// What a hook does with an event.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum HookAction {
+    // POST the event as JSON.
+    Webhook {
+        url: String,
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+    },
+    // Run a program with the event as JSON on stdin and its main fields in RUSTARENA_* variables.
+    Command {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+}
+
+impl HookAction {
+    // What to call the hook in logs, headers are left out as they often hold tokens.
+    fn target(&self) -> &str {
+        match self {
+            HookAction::Webhook { url, .. } => url,
+            HookAction::Command { program, .. } => program,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Hook {
+    // Event types the hook is for, every type when empty.
+    #[serde(default)]
+    pub events: Vec<EventKind>,
+    #[serde(flatten)]
+    pub action: HookAction,
+}
+
+impl Hook {
+    fn wants(&self, event: &WatchEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event.event)
+    }
+}
+
+// Hooks from the --notify config file, e.g.
+// {"hooks": [{"type": "webhook", "url": "https://example.org/hook", "events": ["new_repo"]}]}
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Notifier {
+    pub hooks: Vec<Hook>,
+}
+
+impl Notifier {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let content = fs::read_to_string(path).map_err(|err| format!("Unable to read {}: {}", path.to_string_lossy(), err))?;
+        Ok(serde_json::from_str(&content).map_err(|err| format!("Unable to parse {}: {}", path.to_string_lossy(), err))?)
+    }
+
+    // Send every event to the hooks that want it. A failing hook is logged and doesn't stop
+    // the others. Blocks, keep it off the async workers.
+    pub fn notify(&self, events: &[WatchEvent]) {
+        for event in events {
+            for hook in self.hooks.iter().filter(|hook| hook.wants(event)) {
+                let result = match &hook.action {
+                    HookAction::Webhook { url, headers } => post_webhook(url, headers, event),
+                    HookAction::Command { program, args } => run_command(program, args, event),
+                };
+                if let Err(err) = result {
+                    log::warn!("Notifying {} of {:?} for {} failed: {}", hook.action.target(), event.event, event.repo, err);
+                }
+            }
+        }
+    }
+}
+
+fn post_webhook(url: &str, headers: &BTreeMap<String, String>, event: &WatchEvent) -> Result<(), Box<dyn std::error::Error>> {
+    let client = reqwest::blocking::Client::builder().timeout(HOOK_TIMEOUT).build()?;
+    let mut request = client.post(url).json(event);
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+    let response = request.send()?;
+    if !response.status().is_success() {
+        return Err(format!("{} answered {}", url, response.status()).into());
+    }
+    log::debug!("Posted {:?} for {} to {}", event.event, event.repo, url);
+    Ok(())
+}
+
+fn run_command(program: &str, args: &[String], event: &WatchEvent) -> Result<(), Box<dyn std::error::Error>> {
+    let event_type = serde_json::to_value(event.event)?.as_str().unwrap_or_default().to_string();
+    let mut child = Command::new(program)
+        .args(args)
+        .env("RUSTARENA_EVENT", event_type)
+        .env("RUSTARENA_PARSER", &event.parser)
+        .env("RUSTARENA_REPO", &event.repo)
+        .env("RUSTARENA_URL", &event.url)
+        .env("RUSTARENA_COMMIT", event.commit.as_deref().unwrap_or_default())
+        .stdin(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A command that doesn't read stdin closes it early, that's not a failure.
+        let _ = stdin.write_all(&serde_json::to_vec(event)?);
+    }
+
+    let deadline = Instant::now() + HOOK_TIMEOUT;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            if !status.success() {
+                return Err(format!("{} exited with {}", program, status).into());
+            }
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            child.kill()?;
+            child.wait()?;
+            return Err(format!("{} timed out after {:?}", program, HOOK_TIMEOUT).into());
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use serde_json::Value;
+
+    fn event(kind: EventKind) -> WatchEvent {
+        WatchEvent {
+            event: kind,
+            parser: "sherlock".to_string(),
+            repo: "org/token".to_string(),
+            url: "https://github.com/org/token".to_string(),
+            previous_commit: None,
+            commit: Some("c14cedd7fcd70d5868a00330a8393326cf8615ea".to_string()),
+            detected_at: "2026-01-01T00:00:00Z".to_string(),
+            result: None,
+        }
+    }
+
+    // Accept requests on a local port and hand back each one's request line, headers and body.
+    fn stand_in() -> (String, mpsc::Receiver<(String, Vec<String>, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut headers = Vec::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    headers.push(line.trim().to_lowercase());
+                }
+                let length = headers
+                    .iter()
+                    .find_map(|header| header.strip_prefix("content-length: "))
+                    .map(|length| length.parse().unwrap())
+                    .unwrap_or(0);
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                stream.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n").unwrap();
+                sender.send((request_line.trim().to_string(), headers, String::from_utf8(body).unwrap())).unwrap();
+            }
+        });
+        (url, receiver)
+    }
+
+    #[test]
+    fn webhook_posts_the_event() {
+        let (url, requests) = stand_in();
+        let config = format!(
+            r#"{{"hooks": [
+                {{"type": "webhook", "url": "{url}", "events": ["new_repo"], "headers": {{"X-Token": "secret"}}}},
+                {{"type": "webhook", "url": "{url}/removed", "events": ["removed_repo"]}}
+            ]}}"#
+        );
+        let notifier: Notifier = serde_json::from_str(&config).unwrap();
+        notifier.notify(&[event(EventKind::NewRepo), event(EventKind::CommitChanged)]);
+
+        let (request_line, headers, body) = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(request_line, "POST /hook HTTP/1.1");
+        assert!(headers.contains(&"content-type: application/json".to_string()));
+        assert!(headers.contains(&"x-token: secret".to_string()));
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["event"], "new_repo");
+        assert_eq!(body["repo"], "org/token");
+        assert_eq!(body["commit"], "c14cedd7fcd70d5868a00330a8393326cf8615ea");
+        // Neither hook is for commit_changed.
+        assert!(requests.recv_timeout(Duration::from_millis(500)).is_err());
+    }
+
+    #[test]
+    fn webhook_error_status_is_a_failure() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = [0; 4096];
+            let _ = stream.read(&mut buffer);
+            stream.write_all(b"HTTP/1.1 500 Internal Server Error\r\ncontent-length: 0\r\nconnection: close\r\n\r\n").unwrap();
+        });
+        let err = post_webhook(&url, &BTreeMap::new(), &event(EventKind::NewRepo)).unwrap_err();
+        assert!(err.to_string().contains("500"));
+    }
+
+    #[test]
+    fn command_gets_the_event() {
+        let output = std::env::temp_dir().join(format!("rustarena-notify-{}.json", std::process::id()));
+        let script = format!("cat > {} && test \"$RUSTARENA_EVENT\" = commit_changed", output.to_string_lossy());
+        run_command("sh", &["-c".to_string(), script], &event(EventKind::CommitChanged)).unwrap();
+        let body: Value = serde_json::from_str(&fs::read_to_string(&output).unwrap()).unwrap();
+        fs::remove_file(&output).unwrap();
+        assert_eq!(body["event"], "commit_changed");
+
+        assert!(run_command("sh", &["-c".to_string(), "exit 3".to_string()], &event(EventKind::NewRepo)).is_err());
+    }
+}
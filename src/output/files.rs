@@ -0,0 +1,41 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::output::schema::ResultEnvelope;
+use crate::output::sink::OutputSink;
+use crate::parsers::parse::Repo;
+
+// results/contracts/<parser>_<repo>/<contract>.json, with the result header in _repo.json and
+// the sources in _sources.json. A repository's directory is replaced on every build so contracts that no longer exist don't linger.
+pub struct FilesSink {
+    directory: PathBuf,
+}
+
+impl FilesSink {
+    pub fn new(directory: &Path) -> Self {
+        Self { directory: directory.to_path_buf() }
+    }
+}
+
+impl OutputSink for FilesSink {
+    fn write(&self, repo: &Repo, result: &ResultEnvelope) -> Result<(), Box<dyn std::error::Error>> {
+        let directory = self.directory.join(self.location(repo));
+        if directory.exists() {
+            fs::remove_dir_all(&directory)?;
+        }
+        fs::create_dir_all(&directory)?;
+        log::debug!("Writing {} contracts to {}", result.contracts.len(), directory.to_string_lossy());
+        fs::write(directory.join("_repo.json"), serde_json::to_string_pretty(&result.header)?)?;
+        fs::write(directory.join("_sources.json"), serde_json::to_string_pretty(&result.sources)?)?;
+        for contract in &result.contracts {
+            // Names come from artifact file names, keep them to a single path component.
+            let name = contract.contract_name.replace(['/', '\\'], "_");
+            fs::write(directory.join(format!("{}.json", name)), serde_json::to_string_pretty(contract)?)?;
+        }
+        Ok(())
+    }
+
+    fn location(&self, repo: &Repo) -> String {
+        format!("contracts/{}", repo.file_name())
+    }
+}
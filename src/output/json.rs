@@ -0,0 +1,37 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::output::schema::ResultEnvelope;
+use crate::output::sink::OutputSink;
+use crate::parsers::parse::Repo;
+
+pub struct JsonSink {
+    directory: PathBuf,
+}
+
+impl JsonSink {
+    pub fn new(directory: &Path) -> Self {
+        Self { directory: directory.to_path_buf() }
+    }
+}
+
+impl OutputSink for JsonSink {
+    fn write(&self, repo: &Repo, result: &ResultEnvelope) -> Result<(), Box<dyn std::error::Error>> {
+        // Create a results directory if it doesn't exist. 
+        let results_dir = &self.directory;
+        if !results_dir.exists() {
+            fs::create_dir_all(results_dir)?;
+        }
+
+        // Serialize and write the envelope with the sorted contracts to a JSON file
+        let json_data = serde_json::to_string_pretty(result)?;
+        let json_filename = results_dir.join(self.location(repo));
+        log::debug!("Writing {}", json_filename.to_string_lossy());
+        fs::write(json_filename, json_data)?;
+        Ok(())
+    }
+
+    fn location(&self, repo: &Repo) -> String {
+        format!("{}_contracts.json", repo.file_name())
+    }
+}
@@ -0,0 +1,49 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::output::schema::ResultEnvelope;
+use crate::output::sink::OutputSink;
+use crate::parsers::parse::Repo;
+
+// One compact JSON object per line so consumers can stream a repository's contracts. The
+// first line is the result header, the envelope without its sources and contracts. Sources
+// go to a _sources.jsonl file next to it.
+pub struct JsonLinesSink {
+    directory: PathBuf,
+}
+
+impl JsonLinesSink {
+    pub fn new(directory: &Path) -> Self {
+        Self { directory: directory.to_path_buf() }
+    }
+}
+
+impl OutputSink for JsonLinesSink {
+    fn write(&self, repo: &Repo, result: &ResultEnvelope) -> Result<(), Box<dyn std::error::Error>> {
+        fs::create_dir_all(&self.directory)?;
+        let sources_filename = self.directory.join(format!("{}_sources.jsonl", repo.file_name()));
+        let mut writer = BufWriter::new(File::create(sources_filename)?);
+        for source in &result.sources {
+            serde_json::to_writer(&mut writer, source)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+
+        let filename = self.directory.join(self.location(repo));
+        log::debug!("Writing {}", filename.to_string_lossy());
+        let mut writer = BufWriter::new(File::create(filename)?);
+        serde_json::to_writer(&mut writer, &result.header)?;
+        writer.write_all(b"\n")?;
+        for contract in &result.contracts {
+            serde_json::to_writer(&mut writer, contract)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn location(&self, repo: &Repo) -> String {
+        format!("{}_contracts.jsonl", repo.file_name())
+    }
+}
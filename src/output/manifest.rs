@@ -0,0 +1,159 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::builders::detect::Detection;
+use crate::output::schema::{timestamp, SCHEMA_VERSION};
+use crate::parsers::parse::Repo;
+use crate::quarantine::Outcome;
+
+// What happened to a repository in a run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum RepoOutcome {
+    Built,
+    // A builder was found but produced no contracts.
+    Failed,
+    // No builder knows how to build the repository.
+    Unsupported,
+    // Cloning, building or writing results errored out.
+    Error,
+}
+
+impl From<Outcome> for RepoOutcome {
+    fn from(outcome: Outcome) -> Self {
+        match outcome {
+            Outcome::Failed => RepoOutcome::Failed,
+            Outcome::Unsupported => RepoOutcome::Unsupported,
+        }
+    }
+}
+
+// How a single repository fared, logged in the report at the end of a run and listed in the
+// manifest. Paths are relative to the results directory.
+#[derive(Clone, Debug, Serialize)]
+pub struct RepoReport {
+    pub name: String,
+    pub repo: String,
+    pub parser: String,
+    pub url: String,
+    pub commit: Option<String>,
+    pub builder: Option<String>,
+    pub outcome: RepoOutcome,
+    pub contracts: usize,
+    pub sources: usize,
+    pub missing_sources: usize,
+    pub duration_secs: f64,
+    // Where each output format wrote the repository's results.
+    pub results: Vec<String>,
+    pub failure_record: Option<String>,
+    pub command: Option<String>,
+    pub decision: Option<String>,
+    // Every directory scanned for a builder, with the candidates and their scores.
+    pub detections: Vec<Detection>,
+    pub failure: Option<String>,
+}
+
+impl RepoReport {
+    pub fn new(repo: &Repo, outcome: RepoOutcome, started_at: DateTime<Utc>) -> Self {
+        Self {
+            name: repo.name.clone(),
+            repo: repo.record_name(),
+            parser: repo.parser.clone(),
+            url: repo.url.clone(),
+            commit: repo.commit.clone(),
+            builder: None,
+            outcome,
+            contracts: 0,
+            sources: 0,
+            missing_sources: 0,
+            duration_secs: (Utc::now() - started_at).num_milliseconds() as f64 / 1000.0,
+            results: Vec::new(),
+            failure_record: None,
+            command: None,
+            decision: None,
+            detections: Vec::new(),
+            failure: None,
+        }
+    }
+
+    pub fn failed(repo: &Repo, outcome: RepoOutcome, started_at: DateTime<Utc>, reason: String) -> Self {
+        let mut report = Self::new(repo, outcome, started_at);
+        report.failure = Some(reason);
+        report
+    }
+}
+
+// Bump whenever a field in RunManifest or RepoReport is added, renamed or changes type. Kept
+// apart from the result SCHEMA_VERSION, the two layouts change independently.
+pub const MANIFEST_VERSION: u32 = 1;
+
+// Every repository processed in a run, so scripts don't have to glob results/.
+#[derive(Debug, Serialize)]
+pub struct RunManifest<'a, T = RepoReport> {
+    pub manifest_version: u32,
+    // The layout of the run's result files, see SCHEMA_VERSION.
+    pub result_schema_version: u32,
+    pub started_at: String,
+    pub finished_at: String,
+    pub repos: &'a [T],
+}
+
+// A manifest's entries as written, kept as they are when a watch updates it.
+#[derive(Deserialize)]
+struct StoredManifest {
+    repos: Vec<serde_json::Value>,
+}
+
+// Written to <results>/manifest.json at the end of a run.
+pub fn write_manifest(reports: &[RepoReport], started_at: DateTime<Utc>, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    save_manifest(reports, started_at, path)
+}
+
+// For watch, where each cycle only builds what changed. The cycle's reports replace the
+// manifest's entries for the same repositories and removed ones, given by parser and repo,
+// are dropped, so the manifest keeps describing every repository being watched.
+pub fn update_manifest(reports: &[RepoReport], removed: &[(String, String)], started_at: DateTime<Utc>, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let mut repos = match fs::read_to_string(path) {
+        Ok(content) => match serde_json::from_str::<StoredManifest>(&content) {
+            Ok(manifest) => manifest.repos,
+            Err(err) => {
+                log::warn!("Replacing {}, which can't be parsed: {}", path.to_string_lossy(), err);
+                Vec::new()
+            }
+        },
+        Err(_) => Vec::new(),
+    };
+    let replaced: HashSet<(&str, &str)> = reports
+        .iter()
+        .map(|report| (report.parser.as_str(), report.repo.as_str()))
+        .chain(removed.iter().map(|(parser, repo)| (parser.as_str(), repo.as_str())))
+        .collect();
+    repos.retain(|entry| {
+        let key = (entry["parser"].as_str().unwrap_or_default(), entry["repo"].as_str().unwrap_or_default());
+        !replaced.contains(&key)
+    });
+    for report in reports {
+        repos.push(serde_json::to_value(report)?);
+    }
+    save_manifest(&repos, started_at, path)
+}
+
+fn save_manifest<T: Serialize>(repos: &[T], started_at: DateTime<Utc>, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let manifest = RunManifest {
+        manifest_version: MANIFEST_VERSION,
+        result_schema_version: SCHEMA_VERSION,
+        started_at: timestamp(started_at),
+        finished_at: timestamp(Utc::now()),
+        repos,
+    };
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(&manifest)?)?;
+    log::info!("Wrote {}", path.to_string_lossy());
+    Ok(())
+}
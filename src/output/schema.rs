@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+use chrono::{DateTime, SecondsFormat, Utc};
+use schemars::{schema_for, JsonSchema};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::contract::Contract;
+use crate::parsers::parse::Repo;
+
+// Bump whenever a field in the envelope or Contract is added, renamed or changes type, and
+// regenerate schema/result.schema.json with `cargo run -- schema --out schema/result.schema.json`.
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// Where a repository's contracts came from. Every result file starts with this, the JSON
+/// output as the envelope's other fields and JSON Lines as the first line.
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+pub struct ResultHeader {
+    /// Version of this layout, bumped whenever a field is added, renamed or changes type.
+    pub schema_version: u32,
+    /// The repository's name relative to repos/, or the directory name for local checkouts.
+    pub repo: String,
+    /// The bounty platform the repository was found on, or how it was given on the command line.
+    pub parser: String,
+    pub url: String,
+    /// The commit that was built, null when the source had no git metadata.
+    pub commit: Option<String>,
+    /// The builder that produced the contracts, e.g. "forge" or "committed artifacts (truffle)".
+    pub builder: Option<String>,
+    /// The command that produced the contracts, e.g. "npm run compile". Null for committed artifacts.
+    pub command: Option<String>,
+    /// When cloning, or building a local checkout, started. RFC 3339 in UTC.
+    pub started_at: String,
+    /// When the contracts were written, RFC 3339 in UTC.
+    pub finished_at: String,
+    /// Paths of source files contracts were compiled from that couldn't be read.
+    pub missing_sources: Vec<String>,
+}
+
+/// A source file, kept once however many contracts define or import it. Contracts refer to it
+/// by absolute_path and source_hash.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct Source {
+    pub path: String,
+    /// SHA-256 of the content, lowercase hex.
+    pub hash: String,
+    pub content: String,
+}
+
+/// A repository's results as written to results/<parser>_<repo>_contracts.json.
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+pub struct ResultEnvelope {
+    #[serde(flatten)]
+    pub header: ResultHeader,
+    /// Sorted by path.
+    pub sources: Vec<Source>,
+    /// Interfaces first, then contracts.
+    pub contracts: Vec<Contract>,
+}
+
+impl ResultEnvelope {
+    pub fn new(repo: &Repo, commit: Option<String>, builder: Option<String>, command: Option<String>, started_at: DateTime<Utc>, mut contracts: Vec<Contract>) -> Self {
+        let (sources, missing_sources) = dedup_sources(&mut contracts);
+        let header = ResultHeader {
+            schema_version: SCHEMA_VERSION,
+            repo: repo.record_name(),
+            parser: repo.parser.clone(),
+            url: repo.url.clone(),
+            commit,
+            builder,
+            command,
+            started_at: timestamp(started_at),
+            finished_at: timestamp(Utc::now()),
+            missing_sources,
+        };
+        Self { header, sources, contracts }
+    }
+}
+
+// Move the contracts' source text, imports included, into one Source per path and content and
+// point the contracts at it. Returns the sources and the paths that had no text.
+pub fn dedup_sources(contracts: &mut [Contract]) -> (Vec<Source>, Vec<String>) {
+    let mut sources: BTreeMap<(String, String), String> = BTreeMap::new();
+    let mut missing: Vec<String> = Vec::new();
+    collect_sources(contracts, &mut sources, &mut missing);
+    missing.sort();
+    missing.dedup();
+    let sources = sources.into_iter().map(|((path, hash), content)| Source { path, hash, content }).collect();
+    (sources, missing)
+}
+
+fn collect_sources(contracts: &mut [Contract], sources: &mut BTreeMap<(String, String), String>, missing: &mut Vec<String>) {
+    for contract in contracts {
+        let path = contract.absolute_path.clone().unwrap_or_default();
+        match contract.file_contents.take() {
+            Some(content) => {
+                let hash = sha256_hex(content.as_bytes());
+                contract.source_hash = Some(hash.clone());
+                sources.entry((path, hash)).or_insert(content);
+            }
+            None if contract.source_hash.is_none() && !path.is_empty() => missing.push(path),
+            None => {}
+        }
+        if let Some(imports) = &mut contract.imports {
+            collect_sources(imports, sources, missing);
+        }
+    }
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+// JSON Schema for the result envelope, pretty printed.
+pub fn result_schema() -> String {
+    let schema = schema_for!(ResultEnvelope);
+    format!("{}\n", serde_json::to_string_pretty(&schema).expect("schema serializes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::Kind;
+    use serde_json::Value;
+
+    fn contract(name: &str, path: &str, file_contents: Option<&str>) -> Contract {
+        Contract {
+            contract_name: name.to_string(),
+            kind: Kind::Contract,
+            bytecode: "0x6080".to_string(),
+            deployed_bytecode: Some("0x6080".to_string()),
+            imports: Some(Vec::new()),
+            sourcemap: Some("1:2:0".to_string()),
+            deployed_sourcemap: Some("1:2:0".to_string()),
+            absolute_path: Some(path.to_string()),
+            id: Some(1),
+            source_hash: None,
+            file_contents: file_contents.map(str::to_string),
+        }
+    }
+
+    fn envelope() -> ResultEnvelope {
+        let contract = contract("Token", "contracts/Token.sol", Some("contract Token {}"));
+        let repo = Repo { parser: "sherlock".to_string(), url: "https://github.com/org/token".to_string(), name: "repos/org/token".to_string(), commit: None };
+        ResultEnvelope::new(&repo, None, Some("forge".to_string()), Some("forge build".to_string()), Utc::now(), vec![contract])
+    }
+
+    fn keys(value: &Value) -> Vec<&str> {
+        let mut keys: Vec<&str> = value.as_object().unwrap().keys().map(String::as_str).collect();
+        keys.sort();
+        keys
+    }
+
+    // Changing the layout without regenerating the schema file fails here, see SCHEMA_VERSION.
+    #[test]
+    fn schema_file_is_up_to_date() {
+        assert_eq!(result_schema(), include_str!("../../schema/result.schema.json"));
+    }
+
+    #[test]
+    fn schema_requires_the_version() {
+        let schema: Value = serde_json::from_str(&result_schema()).unwrap();
+        assert_eq!(schema["title"], "ResultEnvelope");
+        let required: Vec<&str> = schema["required"].as_array().unwrap().iter().filter_map(Value::as_str).collect();
+        assert!(required.contains(&"schema_version"));
+        assert!(required.contains(&"contracts"));
+    }
+
+    #[test]
+    fn envelope_layout() {
+        let value = serde_json::to_value(envelope()).unwrap();
+        assert_eq!(keys(&value), ["builder", "command", "commit", "contracts", "finished_at", "missing_sources", "parser", "repo", "schema_version", "sources", "started_at", "url"]);
+        assert_eq!(value["schema_version"], SCHEMA_VERSION);
+        assert_eq!(value["repo"], "org/token");
+        assert_eq!(value["commit"], Value::Null);
+        assert!(value["started_at"].as_str().unwrap().ends_with('Z'));
+    }
+
+    #[test]
+    fn contract_layout() {
+        let value = serde_json::to_value(envelope()).unwrap();
+        let contract = &value["contracts"][0];
+        assert_eq!(
+            keys(contract),
+            ["absolute_path", "bytecode", "contract_name", "deployed_bytecode", "deployed_sourcemap", "id", "imports", "kind", "source_hash", "sourcemap"]
+        );
+        assert_eq!(contract["kind"], "Contract");
+        assert_eq!(contract["source_hash"], value["sources"][0]["hash"]);
+        assert_eq!(keys(&value["sources"][0]), ["content", "hash", "path"]);
+    }
+
+    #[test]
+    fn sources_are_stored_once() {
+        let mut token = contract("Token", "contracts/Token.sol", Some("contract Token {} contract Base {}"));
+        token.imports = Some(vec![contract("IERC20", "contracts/IERC20.sol", Some("interface IERC20 {}"))]);
+        let mut contracts = vec![
+            token,
+            contract("Base", "contracts/Token.sol", Some("contract Token {} contract Base {}")),
+            contract("IERC20", "contracts/IERC20.sol", Some("interface IERC20 {}")),
+            contract("Lib", "lib/Lib.sol", None),
+        ];
+        let (sources, missing) = dedup_sources(&mut contracts);
+
+        let paths: Vec<&str> = sources.iter().map(|source| source.path.as_str()).collect();
+        assert_eq!(paths, ["contracts/IERC20.sol", "contracts/Token.sol"]);
+        assert_eq!(missing, ["lib/Lib.sol"]);
+        assert_eq!(contracts[0].source_hash, contracts[1].source_hash);
+        assert_eq!(contracts[0].imports.as_ref().unwrap()[0].source_hash, contracts[2].source_hash);
+        assert_eq!(sources[0].hash, sha256_hex(b"interface IERC20 {}"));
+        assert!(contracts.iter().all(|contract| contract.file_contents.is_none()));
+        assert_eq!(contracts[3].source_hash, None);
+    }
+}
@@ -0,0 +1,47 @@
+use std::path::Path;
+use clap::ValueEnum;
+
+use crate::contract::{Contract, Kind};
+use crate::output::files::FilesSink;
+use crate::output::json::JsonSink;
+use crate::output::jsonl::JsonLinesSink;
+use crate::output::schema::ResultEnvelope;
+use crate::output::sqlite::SqliteSink;
+use crate::parsers::parse::Repo;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum OutputFormat {
+    // results/<parser>_<repo>_contracts.json, one array per repository.
+    Json,
+    // results/<parser>_<repo>_contracts.jsonl, one contract per line.
+    Jsonl,
+    // results/contracts.db with repos, sources and contracts tables.
+    Sqlite,
+    // results/contracts/<parser>_<repo>/<contract>.json, one file per contract.
+    Files,
+}
+
+// Somewhere built contracts are written. Sinks are shared by every build task.
+pub trait OutputSink: Send + Sync {
+    fn write(&self, repo: &Repo, result: &ResultEnvelope) -> Result<(), Box<dyn std::error::Error>>;
+    // Where the repository's results went, relative to the results directory.
+    fn location(&self, repo: &Repo) -> String;
+}
+
+// A sink writing under results_dir, results/ unless --results-dir says otherwise.
+pub fn open_sink(format: OutputFormat, results_dir: &Path) -> Result<Box<dyn OutputSink>, Box<dyn std::error::Error>> {
+    Ok(match format {
+        OutputFormat::Json => Box::new(JsonSink::new(results_dir)),
+        OutputFormat::Jsonl => Box::new(JsonLinesSink::new(results_dir)),
+        OutputFormat::Sqlite => Box::new(SqliteSink::open(&results_dir.join("contracts.db"))?),
+        OutputFormat::Files => Box::new(FilesSink::new(results_dir)),
+    })
+}
+
+// Interfaces first, the order results have always been written in.
+pub fn sort_contracts(contracts: &mut [Contract]) {
+    contracts.sort_by_key(|contract| match contract.kind {
+        Kind::Interface => 0,
+        Kind::Contract => 1,
+    });
+}
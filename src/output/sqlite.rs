@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use rusqlite::{params, Connection};
+
+use crate::contract::Kind;
+use crate::output::schema::{ResultEnvelope, SCHEMA_VERSION};
+use crate::output::sink::OutputSink;
+use crate::parsers::parse::Repo;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS repos (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    parser TEXT NOT NULL,
+    url TEXT NOT NULL,
+    commit_hash TEXT,
+    builder TEXT,
+    command TEXT,
+    started_at TEXT NOT NULL,
+    finished_at TEXT NOT NULL,
+    UNIQUE (parser, name)
+);
+CREATE TABLE IF NOT EXISTS sources (
+    id INTEGER PRIMARY KEY,
+    repo_id INTEGER NOT NULL REFERENCES repos(id) ON DELETE CASCADE,
+    path TEXT NOT NULL,
+    hash TEXT,
+    content TEXT,
+    UNIQUE (repo_id, path, hash)
+);
+CREATE TABLE IF NOT EXISTS contracts (
+    id INTEGER PRIMARY KEY,
+    repo_id INTEGER NOT NULL REFERENCES repos(id) ON DELETE CASCADE,
+    source_id INTEGER REFERENCES sources(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    bytecode TEXT NOT NULL,
+    deployed_bytecode TEXT,
+    sourcemap TEXT,
+    deployed_sourcemap TEXT,
+    ast_id INTEGER,
+    imports TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS contracts_repo ON contracts(repo_id);
+CREATE INDEX IF NOT EXISTS contracts_name ON contracts(name);
+";
+
+// Every repository in one database. Rebuilding a repository replaces its rows. Sources the
+// build couldn't read are kept with a NULL hash and content.
+pub struct SqliteSink {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteSink {
+    pub fn open(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let connection = Connection::open(path)?;
+        connection.execute_batch("PRAGMA foreign_keys = ON; PRAGMA journal_mode = WAL;")?;
+        // user_version is the result schema version the database was created with.
+        let version: u32 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version != 0 && version != SCHEMA_VERSION {
+            return Err(format!("{} was written with result schema version {}, this is version {}. Move it aside to start a new one", path.to_string_lossy(), version, SCHEMA_VERSION).into());
+        }
+        connection.execute_batch(SCHEMA)?;
+        connection.execute_batch(&format!("PRAGMA user_version = {}", SCHEMA_VERSION))?;
+        Ok(Self { connection: Mutex::new(connection) })
+    }
+}
+
+impl OutputSink for SqliteSink {
+    fn write(&self, repo: &Repo, result: &ResultEnvelope) -> Result<(), Box<dyn std::error::Error>> {
+        let mut connection = self.connection.lock().map_err(|err| err.to_string())?;
+        let transaction = connection.transaction()?;
+        let header = &result.header;
+
+        // The same repository found by two parsers is kept twice, like the other sinks do.
+        transaction.execute("DELETE FROM repos WHERE parser = ?1 AND name = ?2", params![repo.parser, repo.name])?;
+        transaction.execute(
+            "INSERT INTO repos (name, parser, url, commit_hash, builder, command, started_at, finished_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![repo.name, header.parser, header.url, header.commit, header.builder, header.command, header.started_at, header.finished_at],
+        )?;
+        let repo_id = transaction.last_insert_rowid();
+
+        let mut source_ids: HashMap<(&str, &str), i64> = HashMap::new();
+        for source in &result.sources {
+            transaction.execute(
+                "INSERT INTO sources (repo_id, path, hash, content) VALUES (?1, ?2, ?3, ?4)",
+                params![repo_id, source.path, source.hash, source.content],
+            )?;
+            source_ids.insert((&source.path, &source.hash), transaction.last_insert_rowid());
+        }
+        for path in &header.missing_sources {
+            transaction.execute("INSERT INTO sources (repo_id, path) VALUES (?1, ?2)", params![repo_id, path])?;
+        }
+
+        for contract in &result.contracts {
+            let source_id = match (&contract.absolute_path, &contract.source_hash) {
+                (Some(path), Some(hash)) => source_ids.get(&(path.as_str(), hash.as_str())).copied(),
+                _ => None,
+            };
+            let kind = match contract.kind {
+                Kind::Interface => "Interface",
+                Kind::Contract => "Contract",
+            };
+            // Imports are stored by name, the imported contracts have rows of their own.
+            let imports: Vec<&str> = contract.imports.iter().flatten().map(|import| import.contract_name.as_str()).collect();
+            transaction.execute(
+                "INSERT INTO contracts (repo_id, source_id, name, kind, bytecode, deployed_bytecode, sourcemap, deployed_sourcemap, ast_id, imports)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    repo_id,
+                    source_id,
+                    contract.contract_name,
+                    kind,
+                    contract.bytecode,
+                    contract.deployed_bytecode,
+                    contract.sourcemap,
+                    contract.deployed_sourcemap,
+                    contract.id,
+                    serde_json::to_string(&imports)?,
+                ],
+            )?;
+        }
+        transaction.commit()?;
+        log::debug!("Wrote {} contracts for {} to the database", result.contracts.len(), &repo.name);
+        Ok(())
+    }
+
+    fn location(&self, _repo: &Repo) -> String {
+        "contracts.db".to_string()
+    }
+}
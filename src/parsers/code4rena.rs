@@ -3,7 +3,7 @@ use log;
 use scraper::{Html, Selector};
 
 use crate::parsers::parse::Repo;
-use crate::github_api;
+use crate::source_host;
 
 pub struct Code4renaParser {
     pub name: String,
@@ -19,6 +19,12 @@ impl Code4renaParser {
     }
 }
 
+impl Default for Code4renaParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Code4renaParser {
     pub async fn parse_dom(&self) -> Result<Vec<Repo>, Box<dyn std::error::Error + Send + Sync>>  {
         let launch_options= LaunchOptionsBuilder::default()
@@ -57,11 +63,14 @@ impl Code4renaParser {
     
         for element in document.select(&selector) {
             if let Some(link) = element.value().attr("href") {
-                if link.contains("github.com") && link != "https://github.com/code-423n4/" && link != "https://github.com/code-423n4/media-kit" {
-                    log::debug!("Found github link {}", link);
+                if link == "https://github.com/code-423n4/" || link == "https://github.com/code-423n4/media-kit" {
+                    continue;
+                }
+                if let Some(source_link) = source_host::parse_link(link) {
+                    log::debug!("Found {} link {}", source_link.host, link);
                     let parser = self.name.to_string();
-                    let url = link.to_string();
-                    let name = format!("repos/{}", github_api::get_last_path_part(&url.as_str()).unwrap());
+                    let url = source_link.url;
+                    let name = format!("repos/{}", source_link.name);
                     let commit = None;
                     let repo = Repo { parser, url, name, commit };
                     repos.push(repo);
@@ -71,7 +80,7 @@ impl Code4renaParser {
         log::info!("parser found {} repos", repos.len());
         Ok(repos)
     }
-    
+
     fn url(&self) -> &str {
         &self.url
     }
@@ -3,14 +3,13 @@ use serde_derive::Deserialize;
 use serde_derive::Serialize;
 use graphql_client;
 use graphql_client::{GraphQLQuery, Response};
-use std::collections::{HashMap, HashSet};
-use url::Url;
+use std::collections::HashMap;
 use std::error::Error;
 use std::time::Duration;
 use tokio::task;
 
 use crate::parsers::parse::Repo;
-use crate::github_api;
+use crate::source_host::{self, SourceLink};
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -74,6 +73,12 @@ impl HatsParser {
     }
 }
 
+impl Default for HatsParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl HatsParser {
     pub async fn parse_dom(&self) -> Result<Vec<Repo>, Box<dyn Error + Send + Sync>>  {
         let mut repos: Vec<Repo> = Vec::new();
@@ -92,7 +97,7 @@ impl HatsParser {
         });
 
         // A description block of text could contain multiple github links. We want a unique set. 
-        let mut unique_github_links: HashSet<String> = HashSet::new();
+        let mut unique_github_links: HashMap<String, SourceLink> = HashMap::new();
 
         // For each chain we want to query the Hats Graphql Api. 
         for url in &self.urls {
@@ -133,19 +138,12 @@ impl HatsParser {
                                         for severity in hats.severities {
                                             for contract_link in &severity.contracts_covered {
                                                 for (_contract, link) in contract_link.iter() {
-                                                    if link.contains("github.com") {
-                                                        // Parse for the github repo format
-                                                        let parsed_url = Url::parse(&link);
-                                                        if let Ok(url) = parsed_url {
-                                                            let path_segments = url.path_segments().unwrap();
-                                                            let formatted_path = path_segments.take(2).collect::<Vec<_>>().join("/");
-                                                            let formatted_url = format!("{}://{}/{}", url.scheme(), url.host_str().unwrap(), formatted_path);
-                                                            if unique_github_links.insert(formatted_url.to_owned()) {
-                                                                // Only logging on new github urls
-                                                                log::info!("Found github repo: {}", formatted_url);
-                                                            }
-                                                        } else {
-                                                            log::error!("Couldn't parse the url {}", link)
+                                                    // Parse for the repository on whichever host it lives
+                                                    if let Some(source_link) = source_host::parse_link(link) {
+                                                        if !unique_github_links.contains_key(&source_link.url) {
+                                                            // Only logging on new repository urls
+                                                            log::info!("Found {} repo: {}", source_link.host, source_link.url);
+                                                            unique_github_links.insert(source_link.url.to_owned(), source_link);
                                                         }
                                                     }
                                                 }
@@ -163,10 +161,9 @@ impl HatsParser {
             }
         }
         // Similar to other parsers, create repo structs and return a Vec of them
-        for github_link in unique_github_links {
+        for (url, source_link) in unique_github_links {
             let parser = self.name.to_string();
-            let url = github_link.to_string();
-            let name = format!("repos/{}", github_api::get_last_path_part(&url.as_str()).unwrap());
+            let name = format!("repos/{}", source_link.name);
             let commit = None;
             let repo = Repo { parser, url, name, commit };
             log::debug!("Adding repo {:?}", repo);
@@ -1,13 +1,12 @@
 use headless_chrome::{Browser, LaunchOptionsBuilder};
 use log;
 use scraper::{Html, Selector};
-use url::Url;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use tokio::task::{spawn_blocking, spawn};
 use std::sync::Arc;
 use tokio::sync::Semaphore;
 
-use crate::github_api;
+use crate::source_host::{self, SourceLink};
 use crate::parsers::parse::Repo;
 
 pub struct ImmunefiParser {
@@ -24,10 +23,16 @@ impl ImmunefiParser {
     }
 }
 
+impl Default for ImmunefiParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ImmunefiParser {
     pub async fn parse_dom(&self) -> Result<Vec<Repo>, Box<dyn std::error::Error + Send + Sync>> {
         let mut repos: Vec<Repo> = Vec::new();
-        let mut unique_github_links: HashSet<String> = HashSet::new();
+        let mut unique_github_links: HashMap<String, SourceLink> = HashMap::new();
         let url = self.url.clone(); 
 
         // Chrome needs to be run in blocking mode.
@@ -102,27 +107,22 @@ impl ImmunefiParser {
                 let body = response.text().await?;
                 let document = Html::parse_document(&body);
 
-                let mut github_links = HashSet::new();
+                let mut github_links = HashMap::new();
 
                 for element in document.select(&selector) {
                     if let Some(link) = element.value().attr("href") {
-                        if link.contains("github.com") && !link.contains("immunefi-team") {
-                            // Parse for the github repo format
-                            let parsed_url = Url::parse(&link);
-                            if let Ok(url) = parsed_url {
-                                let path_segments = url.path_segments().unwrap();
-                                let formatted_path = path_segments.take(2).collect::<Vec<_>>().join("/");
-                                let formatted_url = format!("{}://{}/{}", url.scheme(), url.host_str().unwrap(), formatted_path);
-                                github_links.insert(formatted_url.to_owned());
-                                log::debug!("Found github url {}", link);
-                            } else {
-                                log::error!("Couldn't parse the url {}", link)
-                            }
+                        if link.contains("immunefi-team") {
+                            continue;
+                        }
+                        // Normalise to the repository url on whichever host it lives
+                        if let Some(source_link) = source_host::parse_link(link) {
+                            log::debug!("Found {} url {}", source_link.host, link);
+                            github_links.insert(source_link.url.to_owned(), source_link);
                         }
                     }
                 }
                 drop(permit);
-                Ok::<HashSet<String>, Box<dyn std::error::Error + Send + Sync>>(github_links)
+                Ok::<HashMap<String, SourceLink>, Box<dyn std::error::Error + Send + Sync>>(github_links)
             });
             tasks.push(task);
         }
@@ -137,10 +137,9 @@ impl ImmunefiParser {
         }
         
         // Turn the results into repos
-        for github_link in unique_github_links {
+        for (url, source_link) in unique_github_links {
             let parser = self.name.to_string();
-            let url = github_link.to_string();
-            let name = format!("repos/{}", github_api::get_last_path_part(&url.as_str()).unwrap());
+            let name = format!("repos/{}", source_link.name);
             let commit = None;
             let repo = Repo { parser, url, name, commit };
             repos.push(repo);
@@ -148,7 +147,7 @@ impl ImmunefiParser {
         log::info!("parser found {} repos", repos.len());
         Ok(repos)
     }
-    
+
     fn url(&self) -> &str {
         &self.url
     }
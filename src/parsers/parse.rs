@@ -1,7 +1,9 @@
 use std::error::Error;
 use std::fmt;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Repo {
     pub parser: String,
     pub url: String,
@@ -9,6 +11,22 @@ pub struct Repo {
     pub commit: Option<String>,
 }
 
+impl Repo {
+    // Name of the repository relative to repos/, or the directory name for local checkouts.
+    pub fn record_name(&self) -> String {
+        let repo_path = Path::new(&self.name);
+        match repo_path.strip_prefix("repos") {
+            Ok(name) => name.to_string_lossy().to_string(),
+            Err(_) => repo_path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default(),
+        }
+    }
+
+    // record_name flattened so it can be used in a file name.
+    pub fn file_name(&self) -> String {
+        format!("{}_{}", self.parser, self.record_name().replace('/', "_"))
+    }
+}
+
 #[derive(Debug)]
 pub struct ParseError {
     message: String,
@@ -9,7 +9,7 @@ use tokio::task;
 use futures::future::try_join_all;
 
 use crate::parsers::parse::ParseError;
-use crate::github_api;
+use crate::source_host;
 use crate::parsers::parse::Repo;
 
 pub struct SherlockParser{
@@ -100,27 +100,22 @@ impl SherlockParser {
             }
     
             let results: Vec<Result<_, Box<dyn Error + Send + Sync>>> = try_join_all(tasks).await?;
-            for result in results {
-                if let Ok(contest_data) = result {
-                    let html: String = markdown::to_html(&contest_data.description);
-                    let document = Html::parse_document(&html);
-                    let selector = Selector::parse("a").unwrap();
-    
-                    for element in document.select(&selector) {
-                        if let Some(link) = element.value().attr("href") {
-                            if link.contains("github.com") {
-                                // Parse the github url for repo and commit
-                                if let Some((url, repo, sha)) = github_api::parse_github_url(link) {
-                                    log::info!("Found github link {}. Cloning {} with sha {}", url, repo, sha);
-                                    let parser = self.name.to_string();
-                                    let name = format!("repos/{}", repo);
-                                    let commit = Some(sha);
-                                    let repo = Repo { parser, url, name, commit };
-                                    repos.push(repo);
-                                } else {
-                                    log::info!("Invalid GitHub URL {}", link);
-                                }
-                            }
+            for contest_data in results.into_iter().flatten() {
+                let html: String = markdown::to_html(&contest_data.description);
+                let document = Html::parse_document(&html);
+                let selector = Selector::parse("a").unwrap();
+
+                for element in document.select(&selector) {
+                    if let Some(link) = element.value().attr("href") {
+                        // Parse the source url for repo and commit
+                        if let Some(source_link) = source_host::parse_link(link) {
+                            log::info!("Found {} link {}. Cloning {} with reference {:?}", source_link.host, link, source_link.path, source_link.reference);
+                            let parser = self.name.to_string();
+                            let url = source_link.url;
+                            let name = format!("repos/{}", source_link.path);
+                            let commit = source_link.reference;
+                            let repo = Repo { parser, url, name, commit };
+                            repos.push(repo);
                         }
                     }
                 }
@@ -137,6 +132,12 @@ impl SherlockParser {
     }
 }
 
+impl Default for SherlockParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 async fn parse_contest(contest_url: String) -> Result<Contest, Box<dyn std::error::Error + Send + Sync>> {
     let contest_response = reqwest::get(&contest_url).await?;
 
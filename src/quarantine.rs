@@ -0,0 +1,182 @@
+use std::fs;
+use std::path::Path;
+use clap::ValueEnum;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Serialize;
+
+use crate::builders::runner::{CommandRecord, Runner};
+use crate::builders::detect::Decision;
+use crate::contract::BuildAttempt;
+use crate::github_api;
+use crate::parsers::parse::Repo;
+
+// What to do with a repository that didn't produce any contracts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum QuarantineAction {
+    // Leave the checkout where it is.
+    Keep,
+    // Move it under repos/error or repos/unsupported.
+    Move,
+    // Remove it from disk.
+    Delete,
+    // Compress it to a tarball under repos/error or repos/unsupported and remove the checkout.
+    Archive,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    // A builder was found but produced no contracts.
+    Failed,
+    // No builder knows how to build the repository.
+    Unsupported,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct QuarantinePolicy {
+    pub on_failure: QuarantineAction,
+    pub on_unsupported: QuarantineAction,
+}
+
+impl QuarantinePolicy {
+    pub fn new(on_failure: QuarantineAction, on_unsupported: QuarantineAction) -> Self {
+        Self { on_failure, on_unsupported }
+    }
+
+    // Local working copies are never touched.
+    pub fn in_place() -> Self {
+        Self::new(QuarantineAction::Keep, QuarantineAction::Keep)
+    }
+
+    pub fn action(&self, outcome: Outcome) -> QuarantineAction {
+        match outcome {
+            Outcome::Failed => self.on_failure,
+            Outcome::Unsupported => self.on_unsupported,
+        }
+    }
+}
+
+// Everything we know about why a repository didn't build. Written to results/failures
+// so the evidence survives whatever happens to the checkout.
+#[derive(Clone, Debug, Serialize)]
+pub struct FailureRecord {
+    pub repo: String,
+    pub parser: String,
+    pub url: String,
+    // The commit that was checked out, or the reference asked for when there's no git checkout.
+    pub commit: Option<String>,
+    pub outcome: Outcome,
+    pub reason: String,
+    pub decision: Decision,
+    pub attempts: Vec<BuildAttempt>,
+    pub commands: Vec<CommandRecord>,
+    pub action: Option<QuarantineAction>,
+    pub location: Option<String>,
+    // Where the record was written, relative to results/. None until it has been.
+    #[serde(skip)]
+    pub record_path: Option<String>,
+}
+
+impl FailureRecord {
+    pub fn new(repo: &Repo, outcome: Outcome, reason: &str, decision: Decision, attempts: Vec<BuildAttempt>, runner: &Runner) -> Self {
+        // The first failing command says more than the builder's generic reason.
+        let reason = match runner.failure_summary() {
+            Some(summary) => format!("{}. {}", reason, summary),
+            None => reason.to_string(),
+        };
+        Self {
+            repo: repo.name.clone(),
+            parser: repo.parser.clone(),
+            url: repo.url.clone(),
+            // Read before quarantine moves or deletes the checkout.
+            commit: github_api::head_commit(&repo.name).or_else(|| repo.commit.clone()),
+            outcome,
+            reason,
+            decision,
+            attempts,
+            commands: runner.records(),
+            action: None,
+            location: None,
+            record_path: None,
+        }
+    }
+}
+
+// Apply the policy for the record's outcome to the repository and write the record under results_dir.
+pub fn quarantine(repo: &Repo, mut record: FailureRecord, policy: &QuarantinePolicy, results_dir: &Path) -> Result<FailureRecord, Box<dyn std::error::Error>> {
+    let action = policy.action(record.outcome);
+    let target = quarantine_directory(repo, record.outcome);
+    log::error!("{}. Applying {:?} to {}", record.reason, action, &repo.name);
+
+    let location = match action {
+        QuarantineAction::Keep => Some(repo.name.clone()),
+        QuarantineAction::Move => {
+            replace_existing(&target)?;
+            if let Some(parent) = Path::new(&target).parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::rename(&repo.name, &target)?;
+            Some(target)
+        }
+        QuarantineAction::Delete => {
+            fs::remove_dir_all(&repo.name)?;
+            None
+        }
+        QuarantineAction::Archive => {
+            let tarball = format!("{}.tar.gz", target);
+            archive_directory(&repo.name, &tarball)?;
+            fs::remove_dir_all(&repo.name)?;
+            Some(tarball)
+        }
+    };
+
+    record.action = Some(action);
+    record.location = location;
+    record.record_path = Some(write_failure_record(repo, &record, results_dir)?);
+    Ok(record)
+}
+
+// repos/error/<name> for failed builds, repos/unsupported/<parser>/<name> for unsupported ones.
+fn quarantine_directory(repo: &Repo, outcome: Outcome) -> String {
+    let base = match outcome {
+        Outcome::Failed => String::from("repos/error"),
+        Outcome::Unsupported => format!("repos/unsupported/{}", &repo.parser),
+    };
+    format!("{}/{}", base, repo.record_name())
+}
+
+// A previous run may have quarantined the same repository already.
+fn replace_existing(target: &str) -> std::io::Result<()> {
+    let target = Path::new(target);
+    if target.is_dir() {
+        fs::remove_dir_all(target)?;
+    } else if target.exists() {
+        fs::remove_file(target)?;
+    }
+    Ok(())
+}
+
+fn archive_directory(directory: &str, tarball: &str) -> Result<(), Box<dyn std::error::Error>> {
+    replace_existing(tarball)?;
+    if let Some(parent) = Path::new(tarball).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let encoder = GzEncoder::new(fs::File::create(tarball)?, Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    let name = Path::new(directory).file_name().unwrap_or_default();
+    builder.append_dir_all(name, directory)?;
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+// Returns the record's path relative to results_dir.
+pub fn write_failure_record(repo: &Repo, record: &FailureRecord, results_dir: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let record_path = format!("failures/{}.json", repo.file_name());
+    let filename = results_dir.join(&record_path);
+    fs::create_dir_all(results_dir.join("failures"))?;
+    log::debug!("Writing failure record {}", filename.to_string_lossy());
+    fs::write(filename, serde_json::to_string_pretty(record)?)?;
+    Ok(record_path)
+}
@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::diff::load_manifest;
+use crate::output::manifest::RepoOutcome;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    Text,
+    Json,
+}
+
+// The parts of a run manifest the report needs, read loosely like the diff does.
+#[derive(Deserialize)]
+struct StoredManifest {
+    started_at: Option<String>,
+    finished_at: Option<String>,
+    repos: Vec<ReportedRepo>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReportedRepo {
+    pub parser: String,
+    pub repo: String,
+    pub url: String,
+    pub commit: Option<String>,
+    pub builder: Option<String>,
+    pub outcome: RepoOutcome,
+    #[serde(default)]
+    pub contracts: usize,
+    #[serde(default)]
+    pub missing_sources: usize,
+    #[serde(default)]
+    pub duration_secs: f64,
+    pub failure: Option<String>,
+    pub failure_record: Option<String>,
+}
+
+// Totals for a run, and the repositories matching the outcome filter.
+#[derive(Clone, Debug, Serialize)]
+pub struct RunReport {
+    pub results: String,
+    pub started_at: Option<String>,
+    pub finished_at: Option<String>,
+    pub repositories: usize,
+    pub outcomes: BTreeMap<RepoOutcome, usize>,
+    pub contracts: usize,
+    pub missing_sources: usize,
+    pub repos: Vec<ReportedRepo>,
+}
+
+// Summarise the run in a results directory or manifest.json. An empty filter lists every repository.
+pub fn report_run(run: &Path, outcomes: &[RepoOutcome]) -> Result<RunReport, Box<dyn std::error::Error>> {
+    let (directory, manifest) = load_manifest::<StoredManifest>(run)?;
+    let mut counts = BTreeMap::new();
+    for repo in &manifest.repos {
+        *counts.entry(repo.outcome).or_insert(0) += 1;
+    }
+    Ok(RunReport {
+        results: directory.to_string_lossy().to_string(),
+        started_at: manifest.started_at,
+        finished_at: manifest.finished_at,
+        repositories: manifest.repos.len(),
+        outcomes: counts,
+        contracts: manifest.repos.iter().map(|repo| repo.contracts).sum(),
+        missing_sources: manifest.repos.iter().map(|repo| repo.missing_sources).sum(),
+        repos: manifest.repos.into_iter().filter(|repo| outcomes.is_empty() || outcomes.contains(&repo.outcome)).collect(),
+    })
+}
+
+pub fn print_report(report: &RunReport, format: ReportFormat) -> Result<(), Box<dyn std::error::Error>> {
+    if format == ReportFormat::Json {
+        println!("{}", serde_json::to_string_pretty(report)?);
+        return Ok(());
+    }
+
+    println!(
+        "{}: run from {} to {}",
+        report.results,
+        report.started_at.as_deref().unwrap_or("unknown"),
+        report.finished_at.as_deref().unwrap_or("unknown")
+    );
+    let outcomes: Vec<String> = RepoOutcome::value_variants()
+        .iter()
+        .map(|outcome| format!("{} {}", report.outcomes.get(outcome).unwrap_or(&0), outcome_name(*outcome)))
+        .collect();
+    println!(
+        "{} repositories ({}), {} contracts, {} missing sources",
+        report.repositories,
+        outcomes.join(", "),
+        report.contracts,
+        report.missing_sources
+    );
+    for repo in &report.repos {
+        let detail = match (&repo.failure, &repo.builder) {
+            (Some(failure), _) => failure.clone(),
+            (None, Some(builder)) => format!("{} contracts with {}", repo.contracts, builder),
+            (None, None) => format!("{} contracts", repo.contracts),
+        };
+        println!("{:<11} {} {} ({:.1}s) {}", outcome_name(repo.outcome), repo.parser, repo.repo, repo.duration_secs, detail);
+    }
+    Ok(())
+}
+
+fn outcome_name(outcome: RepoOutcome) -> String {
+    outcome.to_possible_value().map(|value| value.get_name().to_string()).unwrap_or_default()
+}
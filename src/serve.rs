@@ -0,0 +1,433 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::net::{SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use axum::extract::{Path as UrlPath, Query, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tokio::task::{block_in_place, spawn_blocking};
+use url::Url;
+
+use crate::github_api;
+use crate::output::manifest::RepoReport;
+use crate::output::schema::{sha256_hex, timestamp};
+use crate::parsers::parse::Repo;
+
+// Clones and builds a repository, writing its results like a normal run.
+pub type BuildFn = Arc<dyn Fn(Repo) -> RepoReport + Send + Sync>;
+
+type ApiError = (StatusCode, Json<Value>);
+type ApiResult = Result<Json<Value>, ApiError>;
+
+// Result files parsed once and kept until they change on disk.
+struct Results {
+    directory: PathBuf,
+    cache: Mutex<HashMap<PathBuf, (SystemTime, Arc<Value>)>>,
+}
+
+impl Results {
+    fn read(&self, path: &Path) -> Option<Arc<Value>> {
+        let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok()?;
+        if let Some((cached_at, value)) = self.cache.lock().unwrap().get(path) {
+            if *cached_at == modified {
+                return Some(Arc::clone(value));
+            }
+        }
+        let value: Value = match fs::read_to_string(path).map_err(|err| err.to_string()).and_then(|content| serde_json::from_str(&content).map_err(|err| err.to_string())) {
+            Ok(value) => value,
+            Err(err) => {
+                log::warn!("Unable to read {}: {}", path.to_string_lossy(), err);
+                return None;
+            }
+        };
+        let value = Arc::new(value);
+        self.cache.lock().unwrap().insert(path.to_path_buf(), (modified, Arc::clone(&value)));
+        Some(value)
+    }
+
+    // Every repository with results, by id (Repo::file_name). Built repositories come from
+    // their JSON results, the last run's manifest adds failures and the latest outcome.
+    fn repos(&self) -> BTreeMap<String, Value> {
+        let mut repos = BTreeMap::new();
+        for (id, path) in self.result_files() {
+            if let Some(envelope) = self.read(&path) {
+                let mut summary = header(&envelope);
+                summary["id"] = json!(id);
+                summary["outcome"] = json!("built");
+                summary["contracts"] = json!(envelope["contracts"].as_array().map_or(0, Vec::len));
+                repos.insert(id, summary);
+            }
+        }
+        if let Some(manifest) = self.read(&self.directory.join("manifest.json")) {
+            for entry in manifest["repos"].as_array().into_iter().flatten() {
+                let repo = Repo {
+                    parser: entry["parser"].as_str().unwrap_or_default().to_string(),
+                    name: entry["name"].as_str().unwrap_or_default().to_string(),
+                    url: String::new(),
+                    commit: None,
+                };
+                let mut summary = entry.clone();
+                summary["id"] = json!(repo.file_name());
+                repos.insert(repo.file_name(), summary);
+            }
+        }
+        repos
+    }
+
+    // <id>_contracts.json files in the results directory.
+    fn result_files(&self) -> Vec<(String, PathBuf)> {
+        let entries = fs::read_dir(&self.directory).into_iter().flatten().flatten();
+        entries
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                name.strip_suffix("_contracts.json").map(|id| (id.to_string(), entry.path()))
+            })
+            .collect()
+    }
+
+    fn envelope(&self, id: &str) -> Option<Arc<Value>> {
+        self.read(&self.directory.join(format!("{}_contracts.json", id)))
+    }
+}
+
+// The envelope without its sources and contracts.
+fn header(envelope: &Value) -> Value {
+    let mut header = envelope.clone();
+    if let Some(object) = header.as_object_mut() {
+        object.remove("contracts");
+        object.remove("sources");
+    }
+    header
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum JobStatus {
+    Queued,
+    Running,
+    Done,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct Job {
+    id: u64,
+    url: String,
+    commit: Option<String>,
+    status: JobStatus,
+    queued_at: String,
+    report: Option<RepoReport>,
+}
+
+struct AppState {
+    results: Results,
+    jobs: Mutex<BTreeMap<u64, Job>>,
+    queue: mpsc::UnboundedSender<(u64, Repo)>,
+}
+
+type SharedState = Arc<AppState>;
+
+// Serve the results in results_directory until the process is stopped. Builds asked for over
+// the API run one at a time in the background.
+pub async fn run(listen: SocketAddr, results_directory: PathBuf, build: BuildFn) -> Result<(), Box<dyn std::error::Error>> {
+    // Nothing authenticates POST /builds, so anyone who can reach the port can queue clones.
+    if !listen.ip().is_loopback() {
+        log::warn!("Listening on {}, which isn't a loopback address. Anyone who can reach it can queue builds", listen);
+    }
+    serve(TcpListener::bind(listen)?, results_directory, build).await
+}
+
+async fn serve(listener: TcpListener, results_directory: PathBuf, build: BuildFn) -> Result<(), Box<dyn std::error::Error>> {
+    let listen = listener.local_addr()?;
+    let (queue, mut queued) = mpsc::unbounded_channel::<(u64, Repo)>();
+    let state = Arc::new(AppState {
+        results: Results { directory: results_directory, cache: Mutex::new(HashMap::new()) },
+        jobs: Mutex::new(BTreeMap::new()),
+        queue,
+    });
+
+    let worker_state = Arc::clone(&state);
+    tokio::spawn(async move {
+        while let Some((id, repo)) = queued.recv().await {
+            set_status(&worker_state, id, JobStatus::Running, None);
+            let build = Arc::clone(&build);
+            match spawn_blocking(move || build(repo)).await {
+                Ok(report) => set_status(&worker_state, id, JobStatus::Done, Some(report)),
+                Err(err) => {
+                    log::error!("Build job {} failed: {}", id, err);
+                    set_status(&worker_state, id, JobStatus::Done, None);
+                }
+            }
+        }
+    });
+
+    let app = Router::new()
+        .route("/repos", get(list_repos))
+        .route("/repos/:id", get(get_repo))
+        .route("/repos/:id/contracts", get(repo_contracts))
+        .route("/contracts", get(find_contracts))
+        .route("/sources/:hash", get(get_source))
+        .route("/builds", post(enqueue_build).get(list_builds))
+        .route("/builds/:id", get(get_build))
+        .with_state(state);
+
+    log::info!("Serving results on http://{}", listen);
+    axum::Server::from_tcp(listener)?.serve(app.into_make_service()).await?;
+    Ok(())
+}
+
+fn set_status(state: &AppState, id: u64, status: JobStatus, report: Option<RepoReport>) {
+    if let Some(job) = state.jobs.lock().unwrap().get_mut(&id) {
+        job.status = status;
+        if report.is_some() {
+            job.report = report;
+        }
+    }
+}
+
+fn not_found(what: &str) -> ApiError {
+    (StatusCode::NOT_FOUND, Json(json!({ "error": format!("{} not found", what) })))
+}
+
+#[derive(Deserialize)]
+struct RepoFilter {
+    parser: Option<String>,
+    // An outcome from the manifest: built, failed, unsupported or error.
+    status: Option<String>,
+}
+
+async fn list_repos(State(state): State<SharedState>, Query(filter): Query<RepoFilter>) -> ApiResult {
+    let repos = block_in_place(|| state.results.repos());
+    let repos: Vec<Value> = repos
+        .into_values()
+        .filter(|repo| filter.parser.as_ref().is_none_or(|parser| repo["parser"] == json!(parser)))
+        .filter(|repo| filter.status.as_ref().is_none_or(|status| repo["outcome"] == json!(status)))
+        .collect();
+    Ok(Json(json!(repos)))
+}
+
+async fn get_repo(State(state): State<SharedState>, UrlPath(id): UrlPath<String>) -> ApiResult {
+    let mut repo = block_in_place(|| state.results.repos()).remove(&id).ok_or_else(|| not_found(&id))?;
+    if let Some(envelope) = block_in_place(|| state.results.envelope(&id)) {
+        repo["result"] = header(&envelope);
+    }
+    Ok(Json(repo))
+}
+
+async fn repo_contracts(State(state): State<SharedState>, UrlPath(id): UrlPath<String>) -> ApiResult {
+    let envelope = block_in_place(|| state.results.envelope(&id)).ok_or_else(|| not_found(&id))?;
+    Ok(Json(envelope["contracts"].clone()))
+}
+
+#[derive(Deserialize)]
+struct ContractFilter {
+    name: Option<String>,
+    // SHA-256 of the deployed bytecode, as compared by diff.
+    bytecode_hash: Option<String>,
+    source_hash: Option<String>,
+}
+
+// Contracts across every repository matching all the filters given, with the repository id.
+async fn find_contracts(State(state): State<SharedState>, Query(filter): Query<ContractFilter>) -> ApiResult {
+    if filter.name.is_none() && filter.bytecode_hash.is_none() && filter.source_hash.is_none() {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({ "error": "give at least one of name, bytecode_hash and source_hash" }))));
+    }
+    let matches = block_in_place(|| {
+        let mut matches = Vec::new();
+        for (id, path) in state.results.result_files() {
+            let envelope = match state.results.read(&path) {
+                Some(envelope) => envelope,
+                None => continue,
+            };
+            for contract in envelope["contracts"].as_array().into_iter().flatten() {
+                let bytecode = contract["deployed_bytecode"].as_str().or(contract["bytecode"].as_str()).unwrap_or_default();
+                let matched = filter.name.as_ref().is_none_or(|name| contract["contract_name"] == json!(name))
+                    && filter.source_hash.as_ref().is_none_or(|hash| contract["source_hash"] == json!(hash.to_lowercase()))
+                    && filter.bytecode_hash.as_ref().is_none_or(|hash| hash.trim_start_matches("0x").eq_ignore_ascii_case(&sha256_hex(bytecode.as_bytes())));
+                if matched {
+                    matches.push(json!({ "repo": id, "contract": contract }));
+                }
+            }
+        }
+        matches
+    });
+    Ok(Json(json!(matches)))
+}
+
+async fn get_source(State(state): State<SharedState>, UrlPath(hash): UrlPath<String>) -> ApiResult {
+    let hash = hash.to_lowercase();
+    let source = block_in_place(|| {
+        state.results.result_files().into_iter().find_map(|(_, path)| {
+            let envelope = state.results.read(&path)?;
+            let sources = envelope["sources"].as_array()?;
+            sources.iter().find(|source| source["hash"] == json!(hash)).cloned()
+        })
+    });
+    source.map(Json).ok_or_else(|| not_found(&hash))
+}
+
+#[derive(Deserialize)]
+struct BuildRequest {
+    url: String,
+    commit: Option<String>,
+}
+
+async fn enqueue_build(State(state): State<SharedState>, Json(request): Json<BuildRequest>) -> Result<(StatusCode, Json<Value>), ApiError> {
+    let not_a_link = || (StatusCode::BAD_REQUEST, Json(json!({ "error": format!("{} is not an http(s) repository link", request.url) })));
+    // Only remote repositories, file:// and other local URLs would have the server clone its own disk.
+    if !Url::parse(&request.url).is_ok_and(|url| matches!(url.scheme(), "http" | "https")) {
+        return Err(not_a_link());
+    }
+    let mut repo = github_api::repo_from_link(&request.url, "api").ok_or_else(not_a_link)?;
+    if request.commit.is_some() {
+        repo.commit = request.commit.clone();
+    }
+    let job = {
+        let mut jobs = state.jobs.lock().unwrap();
+        let id = jobs.keys().next_back().map_or(1, |id| id + 1);
+        let job = Job { id, url: request.url, commit: request.commit, status: JobStatus::Queued, queued_at: timestamp(Utc::now()), report: None };
+        jobs.insert(id, job.clone());
+        job
+    };
+    log::info!("Queued build {} of {}", job.id, &repo.url);
+    state.queue.send((job.id, repo)).map_err(|_| (StatusCode::SERVICE_UNAVAILABLE, Json(json!({ "error": "the build queue has stopped" }))))?;
+    Ok((StatusCode::ACCEPTED, Json(json!(job))))
+}
+
+async fn list_builds(State(state): State<SharedState>) -> ApiResult {
+    let jobs: Vec<Job> = state.jobs.lock().unwrap().values().cloned().collect();
+    Ok(Json(json!(jobs)))
+}
+
+async fn get_build(State(state): State<SharedState>, UrlPath(id): UrlPath<u64>) -> ApiResult {
+    let job = state.jobs.lock().unwrap().get(&id).cloned();
+    job.map(|job| Json(json!(job))).ok_or_else(|| not_found(&format!("build {}", id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{Contract, Kind};
+    use crate::output::manifest::RepoOutcome;
+    use crate::output::schema::ResultEnvelope;
+
+    // A results directory with one built repository and a failed one only in the manifest.
+    fn results(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("rustarena-serve-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let repo = Repo { parser: "sherlock".to_string(), url: "https://github.com/org/token".to_string(), name: "repos/org/token".to_string(), commit: None };
+        let contract = Contract {
+            contract_name: "Token".to_string(),
+            kind: Kind::Contract,
+            bytecode: "0x6080".to_string(),
+            deployed_bytecode: Some("0x6080".to_string()),
+            imports: Some(Vec::new()),
+            sourcemap: None,
+            deployed_sourcemap: None,
+            absolute_path: Some("contracts/Token.sol".to_string()),
+            id: Some(1),
+            source_hash: None,
+            file_contents: Some("contract Token {}".to_string()),
+        };
+        let envelope = ResultEnvelope::new(&repo, None, Some("forge".to_string()), Some("forge build".to_string()), Utc::now(), vec![contract]);
+        fs::write(directory.join(format!("{}_contracts.json", repo.file_name())), serde_json::to_string(&envelope).unwrap()).unwrap();
+        let manifest = json!({ "repos": [
+            { "parser": "sherlock", "name": "repos/org/token", "outcome": "built" },
+            { "parser": "code4rena", "name": "repos/org/vault", "outcome": "failed" },
+        ] });
+        fs::write(directory.join("manifest.json"), manifest.to_string()).unwrap();
+        directory
+    }
+
+    // Serve a results directory on a local port, building nothing.
+    fn start(directory: &Path) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let build: BuildFn = Arc::new(|repo| RepoReport::failed(&repo, RepoOutcome::Error, Utc::now(), "not built in tests".to_string()));
+        let directory = directory.to_path_buf();
+        tokio::spawn(async move { serve(listener, directory, build).await.map_err(|err| err.to_string()) });
+        url
+    }
+
+    async fn get_json(url: &str) -> (StatusCode, Value) {
+        let response = reqwest::get(url).await.unwrap();
+        let status = StatusCode::from_u16(response.status().as_u16()).unwrap();
+        (status, response.json().await.unwrap())
+    }
+
+    // Handlers read with block_in_place, which needs the multi-threaded runtime.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn repos_are_filtered() {
+        let directory = results("filters");
+        let url = start(&directory);
+        let (status, repos) = get_json(&format!("{}/repos", url)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(repos.as_array().unwrap().len(), 2);
+
+        let (_, repos) = get_json(&format!("{}/repos?parser=code4rena", url)).await;
+        let ids: Vec<&str> = repos.as_array().unwrap().iter().filter_map(|repo| repo["id"].as_str()).collect();
+        assert_eq!(ids, ["code4rena_org_vault"]);
+
+        let (_, repos) = get_json(&format!("{}/repos?status=built", url)).await;
+        assert_eq!(repos[0]["id"], "sherlock_org_token");
+        assert_eq!(repos.as_array().unwrap().len(), 1);
+
+        let (_, contracts) = get_json(&format!("{}/contracts?name=Token", url)).await;
+        assert_eq!(contracts[0]["repo"], "sherlock_org_token");
+        let (status, _) = get_json(&format!("{}/contracts", url)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn unknown_ids_are_not_found() {
+        let directory = results("missing");
+        let url = start(&directory);
+        for path in ["/repos/sherlock_org_nothing", "/repos/code4rena_org_vault/contracts", "/sources/00", "/builds/1"] {
+            let (status, body) = get_json(&format!("{}{}", url, path)).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{}", path);
+            assert!(body["error"].is_string());
+        }
+        // Failed repositories are still listed, just without results.
+        let (status, repo) = get_json(&format!("{}/repos/code4rena_org_vault", url)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(repo["outcome"], "failed");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sources_are_found_by_hash() {
+        let directory = results("sources");
+        let url = start(&directory);
+        let hash = sha256_hex(b"contract Token {}");
+        let (status, source) = get_json(&format!("{}/sources/{}", url, hash.to_uppercase())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(source["path"], "contracts/Token.sol");
+        assert_eq!(source["content"], "contract Token {}");
+
+        let (_, contracts) = get_json(&format!("{}/contracts?source_hash={}", url, hash)).await;
+        assert_eq!(contracts.as_array().unwrap().len(), 1);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn builds_need_a_remote_url() {
+        let directory = results("builds");
+        let url = start(&directory);
+        let client = reqwest::Client::new();
+        for link in ["file:///etc", "ssh://github.com/org/token", "/tmp/fx"] {
+            let response = client.post(format!("{}/builds", url)).json(&json!({ "url": link })).send().await.unwrap();
+            assert_eq!(response.status().as_u16(), 400, "{}", link);
+        }
+        let response = client.post(format!("{}/builds", url)).json(&json!({ "url": "https://github.com/org/token" })).send().await.unwrap();
+        assert_eq!(response.status().as_u16(), 202);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
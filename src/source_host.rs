@@ -0,0 +1,270 @@
+use std::fmt;
+use std::sync::RwLock;
+use url::Url;
+
+// The kinds of git hosting we know how to read links from. Each flavour lays out its
+// web URLs differently, so knowing the flavour tells us where the repository path ends
+// and where a branch/commit reference lives.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SourceHost {
+    GitHub,
+    GitLab,
+    Bitbucket,
+    Gitea,
+    // Any other host that serves plain git over https (links ending in .git).
+    Git,
+}
+
+impl fmt::Display for SourceHost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SourceHost::GitHub => "github",
+            SourceHost::GitLab => "gitlab",
+            SourceHost::Bitbucket => "bitbucket",
+            SourceHost::Gitea => "gitea",
+            SourceHost::Git => "git",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl std::str::FromStr for SourceHost {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "github" => Ok(SourceHost::GitHub),
+            "gitlab" => Ok(SourceHost::GitLab),
+            "bitbucket" => Ok(SourceHost::Bitbucket),
+            "gitea" | "forgejo" | "codeberg" => Ok(SourceHost::Gitea),
+            "git" => Ok(SourceHost::Git),
+            _ => Err(format!("Unknown source host kind {}", s)),
+        }
+    }
+}
+
+// A link to a repository that has been recognised and normalised.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceLink {
+    pub host: SourceHost,
+    // Clonable https URL of the repository.
+    pub url: String,
+    // Repository path on the host, e.g. owner/repo or group/subgroup/repo.
+    pub path: String,
+    // Last path part of the repository, used to name the clone directory.
+    pub name: String,
+    // Branch, tag or commit if the link pointed at one.
+    pub reference: Option<String>,
+}
+
+impl SourceLink {
+    // URL of a tarball snapshot of the repository at reference, or the default branch
+    // when there is none. Plain git hosts don't serve archives.
+    pub fn archive_url(&self, reference: Option<&str>) -> Option<String> {
+        let reference = reference.unwrap_or("HEAD");
+        let base = self.url.trim_end_matches(".git");
+        match self.host {
+            SourceHost::GitHub | SourceHost::Gitea => Some(format!("{}/archive/{}.tar.gz", base, reference)),
+            SourceHost::GitLab => Some(format!("{}/-/archive/{}/{}-{}.tar.gz", base, reference, self.name, reference)),
+            SourceHost::Bitbucket => Some(format!("{}/get/{}.tar.gz", base, reference)),
+            SourceHost::Git => None,
+        }
+    }
+}
+
+// Well known public hosts. Self-hosted instances are added with register_host.
+const KNOWN_HOSTS: [(&str, SourceHost); 5] = [
+    ("github.com", SourceHost::GitHub),
+    ("gitlab.com", SourceHost::GitLab),
+    ("bitbucket.org", SourceHost::Bitbucket),
+    ("codeberg.org", SourceHost::Gitea),
+    ("gitea.com", SourceHost::Gitea),
+];
+
+static CUSTOM_HOSTS: RwLock<Vec<(String, SourceHost)>> = RwLock::new(Vec::new());
+
+// Register a self-hosted instance, e.g. ("git.example.org", SourceHost::Gitea).
+pub fn register_host(host: &str, kind: SourceHost) {
+    let host = host.to_lowercase();
+    let mut hosts = CUSTOM_HOSTS.write().unwrap();
+    hosts.retain(|(existing, _)| *existing != host);
+    hosts.push((host, kind));
+}
+
+// Parse a host registration from the command line in the form kind=host.
+pub fn parse_host_arg(arg: &str) -> Result<(String, SourceHost), String> {
+    let (kind, host) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("Expected kind=host, got {}", arg))?;
+    Ok((host.to_string(), kind.parse()?))
+}
+
+pub fn host_kind(host: &str) -> Option<SourceHost> {
+    let host = host.to_lowercase();
+    let host = host.strip_prefix("www.").unwrap_or(&host);
+    if let Some((_, kind)) = KNOWN_HOSTS.iter().find(|(known, _)| *known == host) {
+        return Some(kind.clone());
+    }
+    let hosts = CUSTOM_HOSTS.read().unwrap();
+    hosts.iter().find(|(custom, _)| custom == host).map(|(_, kind)| kind.clone())
+}
+
+// Recognise a link to a repository on any supported host and normalise it to a
+// clonable URL plus an optional reference.
+pub fn parse_link(link: &str) -> Option<SourceLink> {
+    let url = Url::parse(link.trim()).ok()?;
+    if url.scheme() != "https" && url.scheme() != "http" {
+        return None;
+    }
+    let host = url.host_str()?.to_lowercase();
+    let segments: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+
+    let kind = match host_kind(&host) {
+        Some(kind) => kind,
+        None if segments.last().is_some_and(|s| s.ends_with(".git")) => SourceHost::Git,
+        None => return None,
+    };
+
+    let (repo_segments, reference) = match kind {
+        SourceHost::GitHub => split_at_marker(&segments, 2, &["tree", "blob", "commit", "releases"]),
+        SourceHost::Gitea => split_at_marker(&segments, 2, &["src", "commit", "tree", "releases"]),
+        SourceHost::Bitbucket => split_at_marker(&segments, 2, &["src", "commits", "branch"]),
+        SourceHost::GitLab => split_gitlab(&segments),
+        SourceHost::Git => (segments.clone(), None),
+    };
+
+    if repo_segments.len() < 2 {
+        return None;
+    }
+
+    let path = repo_segments.join("/");
+    let path = path.strip_suffix(".git").unwrap_or(&path).to_string();
+    let name = path.rsplit('/').next()?.to_string();
+    let url = match kind {
+        SourceHost::Git => format!("{}://{}/{}.git", url.scheme(), with_port(&host, &url), path),
+        // Known hosts are matched without www. so links with and without it are the same repository.
+        _ => format!("https://{}/{}", with_port(host.strip_prefix("www.").unwrap_or(&host), &url), path),
+    };
+
+    Some(SourceLink { host: kind, url, path, name, reference })
+}
+
+fn with_port(host: &str, url: &Url) -> String {
+    match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    }
+}
+
+// GitHub, Gitea and Bitbucket have owner/repo followed by a marker segment and then the
+// reference. Gitea (src/branch/main) and GitHub releases (releases/tag/v1) add a
+// segment between the marker and the reference.
+fn split_at_marker<'a>(segments: &[&'a str], depth: usize, markers: &[&str]) -> (Vec<&'a str>, Option<String>) {
+    if segments.len() < depth {
+        return (segments.to_vec(), None);
+    }
+    let repo = segments[..depth].to_vec();
+    let reference = match segments.get(depth) {
+        Some(marker) if markers.contains(marker) => {
+            let mut rest = &segments[depth + 1..];
+            let mut marker = *marker;
+            if let Some(kind @ (&"branch" | &"tag" | &"commit")) = rest.first() {
+                marker = kind;
+                rest = &rest[1..];
+            }
+            reference_from(marker, rest)
+        }
+        _ => None,
+    };
+    (repo, reference)
+}
+
+// GitLab allows nested groups so the repository path ends at the "-" separator.
+fn split_gitlab<'a>(segments: &[&'a str]) -> (Vec<&'a str>, Option<String>) {
+    match segments.iter().position(|s| *s == "-") {
+        Some(index) => {
+            let repo = segments[..index].to_vec();
+            let reference = match segments.get(index + 1) {
+                Some(marker @ (&"tree" | &"blob" | &"commit")) => reference_from(marker, &segments[index + 2..]),
+                _ => None,
+            };
+            (repo, reference)
+        }
+        None => (segments.to_vec(), None),
+    }
+}
+
+// A commit is one segment. Branch and tag names may contain slashes and can't be told apart
+// from a directory or file path after them, so the rest of the path is kept and cloning
+// settles on the longest part of it that names a reference.
+fn reference_from(marker: &str, rest: &[&str]) -> Option<String> {
+    match marker {
+        "commit" | "commits" => rest.first().map(|r| r.to_string()),
+        _ if rest.is_empty() => None,
+        _ => Some(rest.join("/")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(link: &str) -> SourceLink {
+        parse_link(link).unwrap_or_else(|| panic!("{} wasn't recognised", link))
+    }
+
+    #[test]
+    fn github_links() {
+        let plain = link("https://github.com/Org/Token");
+        assert_eq!((plain.host, plain.url.as_str(), plain.path.as_str(), plain.name.as_str()), (SourceHost::GitHub, "https://github.com/Org/Token", "Org/Token", "Token"));
+        assert_eq!(plain.reference, None);
+
+        assert_eq!(link("https://www.github.com/org/token.git").url, "https://github.com/org/token");
+        assert_eq!(link("https://github.com/org/token/commit/c14cedd/").reference.as_deref(), Some("c14cedd"));
+        assert_eq!(link("https://github.com/org/token/releases/tag/v1.2").reference.as_deref(), Some("v1.2"));
+        // Branch names with slashes are kept whole, along with any path after them.
+        assert_eq!(link("https://github.com/org/token/tree/feature/audit").reference.as_deref(), Some("feature/audit"));
+        assert_eq!(link("https://github.com/org/token/blob/main/src/Token.sol").reference.as_deref(), Some("main/src/Token.sol"));
+        assert_eq!(link("https://github.com/org/token/pulls").reference, None);
+    }
+
+    #[test]
+    fn gitlab_subgroups() {
+        let nested = link("https://gitlab.com/group/subgroup/token/-/tree/release/v2");
+        assert_eq!(nested.path, "group/subgroup/token");
+        assert_eq!(nested.name, "token");
+        assert_eq!(nested.url, "https://gitlab.com/group/subgroup/token");
+        assert_eq!(nested.reference.as_deref(), Some("release/v2"));
+        assert_eq!(link("https://gitlab.com/group/token/-/commit/c14cedd/diffs").reference.as_deref(), Some("c14cedd"));
+        assert_eq!(link("https://gitlab.com/group/subgroup/token").path, "group/subgroup/token");
+    }
+
+    #[test]
+    fn gitea_and_bitbucket_links() {
+        let branch = link("https://codeberg.org/org/token/src/branch/fix/reentrancy");
+        assert_eq!((branch.host, branch.reference.as_deref()), (SourceHost::Gitea, Some("fix/reentrancy")));
+        assert_eq!(link("https://codeberg.org/org/token/src/commit/c14cedd/contracts/Token.sol").reference.as_deref(), Some("c14cedd"));
+        assert_eq!(link("https://codeberg.org/org/token/src/tag/v1").reference.as_deref(), Some("v1"));
+
+        let bitbucket = link("https://bitbucket.org/team/token/commits/c14cedd");
+        assert_eq!((bitbucket.host.clone(), bitbucket.reference.as_deref()), (SourceHost::Bitbucket, Some("c14cedd")));
+        assert_eq!(bitbucket.archive_url(None).as_deref(), Some("https://bitbucket.org/team/token/get/HEAD.tar.gz"));
+    }
+
+    #[test]
+    fn other_hosts() {
+        let plain = link("https://git.example.net:8443/org/token.git");
+        assert_eq!((plain.host.clone(), plain.url.as_str(), plain.name.as_str()), (SourceHost::Git, "https://git.example.net:8443/org/token.git", "token"));
+        assert_eq!(plain.archive_url(None), None);
+
+        assert_eq!(parse_link("https://git.example.com/org/token"), None);
+        register_host("Git.Example.com", SourceHost::Gitea);
+        assert_eq!(link("https://git.example.com/org/token/src/branch/main").reference.as_deref(), Some("main"));
+
+        assert_eq!(parse_link("https://example.org/org/token"), None);
+        assert_eq!(parse_link("ssh://github.com/org/token"), None);
+        assert_eq!(parse_link("https://github.com/org"), None);
+        assert_eq!(parse_host_arg("gitlab=git.example.org"), Ok(("git.example.org".to_string(), SourceHost::GitLab)));
+        assert!(parse_host_arg("git.example.org").is_err());
+    }
+}
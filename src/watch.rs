@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::github_api;
+use crate::output::manifest::{RepoOutcome, RepoReport};
+use crate::output::schema::timestamp;
+use crate::parsers::parse::Repo;
+
+// What the last watch cycle saw of a repository.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TrackedRepo {
+    pub parser: String,
+    pub url: String,
+    pub name: String,
+    // Upstream commit when last built, None if ls-remote couldn't tell.
+    pub commit: Option<String>,
+    pub first_seen: String,
+    pub last_built: Option<String>,
+    pub outcome: Option<RepoOutcome>,
+}
+
+// Repositories from previous cycles by Repo::file_name, kept between runs in the state file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct WatchState {
+    pub repos: BTreeMap<String, TrackedRepo>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    // Discovered for the first time.
+    NewRepo,
+    // The upstream commit moved since the last build.
+    CommitChanged,
+    // No longer listed by its parser.
+    RemovedRepo,
+}
+
+// A repository that needs building, and why.
+pub struct Change {
+    pub kind: EventKind,
+    pub repo: Repo,
+    pub previous_commit: Option<String>,
+    pub commit: Option<String>,
+}
+
+// How the build a change triggered went.
+#[derive(Clone, Debug, Serialize)]
+pub struct ResultSummary {
+    pub outcome: RepoOutcome,
+    pub builder: Option<String>,
+    pub contracts: usize,
+    pub results: Vec<String>,
+    pub failure: Option<String>,
+}
+
+// Emitted for every change a cycle finds, after the repository was built.
+#[derive(Clone, Debug, Serialize)]
+pub struct WatchEvent {
+    pub event: EventKind,
+    pub parser: String,
+    pub repo: String,
+    pub url: String,
+    pub previous_commit: Option<String>,
+    pub commit: Option<String>,
+    pub detected_at: String,
+    pub result: Option<ResultSummary>,
+}
+
+impl WatchState {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        if !path.exists() {
+            log::info!("No watch state in {}, every repository discovered counts as new", path.to_string_lossy());
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content).map_err(|err| format!("Unable to parse {}: {}", path.to_string_lossy(), err))?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Write then rename so an interrupted daemon doesn't leave half a state file.
+        let temporary = path.with_extension("json.tmp");
+        fs::write(&temporary, serde_json::to_string_pretty(self)?)?;
+        fs::rename(temporary, path)?;
+        Ok(())
+    }
+
+    // Compare a discovery with the state. New repositories and those whose upstream commit
+    // moved are returned for building, ones that disappeared are dropped from the state and
+    // returned as events straight away. Repositories of parsers that failed this time aren't
+    // counted as gone. Runs ls-remote for every repository, so blocks.
+    pub fn changes(&mut self, discovered: Vec<Repo>, failed_parsers: &[String]) -> (Vec<Change>, Vec<WatchEvent>) {
+        let mut changes = Vec::new();
+        let mut seen: Vec<String> = Vec::new();
+        for repo in discovered {
+            let key = repo.file_name();
+            if seen.contains(&key) {
+                continue;
+            }
+            seen.push(key.clone());
+
+            let commit = match github_api::remote_commit(&repo) {
+                Ok(commit) => Some(commit),
+                Err(err) => {
+                    log::warn!("Unable to look up the upstream commit of {}: {}", &repo.url, err);
+                    None
+                }
+            };
+            match self.repos.get(&key) {
+                None => changes.push(Change { kind: EventKind::NewRepo, repo, previous_commit: None, commit }),
+                // Only a commit we could look up counts as a change, a host being down doesn't.
+                Some(tracked) if commit.is_some() && tracked.commit != commit => {
+                    let previous_commit = tracked.commit.clone();
+                    changes.push(Change { kind: EventKind::CommitChanged, repo, previous_commit, commit })
+                }
+                Some(_) => log::debug!("{} is unchanged", &key),
+            }
+        }
+
+        let removed: Vec<String> = self
+            .repos
+            .iter()
+            .filter(|(key, tracked)| !seen.contains(key) && !failed_parsers.contains(&tracked.parser))
+            .map(|(key, _)| key.clone())
+            .collect();
+        let events = removed
+            .into_iter()
+            .filter_map(|key| self.repos.remove(&key))
+            .map(|tracked| WatchEvent {
+                event: EventKind::RemovedRepo,
+                repo: Repo { parser: tracked.parser.clone(), url: tracked.url.clone(), name: tracked.name.clone(), commit: None }.record_name(),
+                parser: tracked.parser,
+                url: tracked.url,
+                previous_commit: tracked.commit,
+                commit: None,
+                detected_at: timestamp(Utc::now()),
+                result: None,
+            })
+            .collect();
+        (changes, events)
+    }
+
+    // Record a change once its build finished and turn it into an event.
+    pub fn record(&mut self, change: Change, report: &RepoReport) -> WatchEvent {
+        let now = timestamp(Utc::now());
+        let repo = &change.repo;
+        let first_seen = self.repos.get(&repo.file_name()).map(|tracked| tracked.first_seen.clone()).unwrap_or_else(|| now.clone());
+        // The commit the build checked out, upstream may have moved since ls-remote. Archives
+        // have no git metadata and report the reference that was asked for, so for them the
+        // ls-remote commit is the best there is.
+        let checked_out = report.commit.clone().filter(|commit| commit.len() == 40 && commit.chars().all(|c| c.is_ascii_hexdigit()));
+        let commit = checked_out.or_else(|| change.commit.clone());
+        // Clone and I/O errors leave the state alone so the repository is tried again next cycle.
+        if report.outcome != RepoOutcome::Error {
+            self.repos.insert(
+                repo.file_name(),
+                TrackedRepo {
+                    parser: repo.parser.clone(),
+                    url: repo.url.clone(),
+                    name: repo.name.clone(),
+                    commit: commit.clone(),
+                    first_seen,
+                    last_built: Some(now.clone()),
+                    outcome: Some(report.outcome),
+                },
+            );
+        }
+        WatchEvent {
+            event: change.kind,
+            parser: repo.parser.clone(),
+            repo: repo.record_name(),
+            url: repo.url.clone(),
+            previous_commit: change.previous_commit,
+            commit,
+            detected_at: now,
+            result: Some(ResultSummary {
+                outcome: report.outcome,
+                builder: report.builder.clone(),
+                contracts: report.contracts,
+                results: report.results.clone(),
+                failure: report.failure.clone(),
+            }),
+        }
+    }
+}
+
+// Append events to the events log, one JSON object per line, and log them.
+pub fn emit_events(events: &[WatchEvent], log_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    if events.is_empty() {
+        return Ok(());
+    }
+    if let Some(parent) = log_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(log_path)?;
+    for event in events {
+        match &event.result {
+            Some(result) => log::info!("{:?}: {} {} at {}, {:?} with {} contracts", event.event, event.parser, event.repo, event.commit.as_deref().unwrap_or("unknown commit"), result.outcome, result.contracts),
+            None => log::info!("{:?}: {} {}", event.event, event.parser, event.repo),
+        }
+        serde_json::to_writer(&mut file, event)?;
+        file.write_all(b"\n")?;
+    }
+    Ok(())
+}